@@ -1,11 +1,21 @@
-use ratatui::widgets::{Block, Paragraph};
+use ratatui::widgets::{Block, Clear, Paragraph};
 use serde::{Deserialize, Serialize};
 use core::fmt;
-use std::cmp::min;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use std::cmp::{min, max};
 use crate::command::Command;
-use crate::todolist::TodoList;
+use crate::todolist::{ListHit, TodoList, TodoListView, Visibility};
 use crate::config;
 use crate::command::CommandPrompt;
+use crate::settings::Settings;
+use crate::status;
+use crate::tags::TagBrowser;
+use crate::archive::{ArchiveView, ArchiveViewWidget, ArchivedTodo};
+use crate::timesheet::{self, Timesheet};
+use crate::pomodoro::{self, Phase, Pomodoro};
+use crate::todo::{self, Due, Estimate, Recurrence, TodoDetail};
 
 #[derive(Serialize, Deserialize)]
 pub struct App {
@@ -14,6 +24,65 @@ pub struct App {
     pub todolists: Vec<TodoList>,
     pub todolist_idx: Option<usize>,
     pub nominal_line_num: usize,
+    #[serde(default)]
+    pub settings: Settings,
+    // Index of the leftmost todolist on screen, kept in sync by the widget
+    #[serde(skip)]
+    todolist_offset: Cell<usize>,
+    // Index of the leftmost tab on screen, kept in sync the same way
+    #[serde(skip)]
+    tab_offset: Cell<usize>,
+    // Draws only the selected todolist across the whole screen
+    #[serde(skip)]
+    pub zen: bool,
+    // Todolists and tabs on screen after the last render, used for mouse hit testing
+    #[serde(skip)]
+    visible_todolists: RefCell<Vec<usize>>,
+    #[serde(skip)]
+    tab_areas: RefCell<Vec<(usize, Rect)>>,
+    // Todolist and todo index picked up by a mouse press, dropped on release
+    #[serde(skip)]
+    drag_source: Option<(usize, usize)>,
+    // Only todos with this tag are shown, across every todolist
+    #[serde(skip)]
+    pub filter: Option<String>,
+    #[serde(skip)]
+    pub tag_browser: TagBrowser,
+    #[serde(skip)]
+    pub archive_view: ArchiveView,
+    #[serde(skip)]
+    pub timesheet: Timesheet,
+    #[serde(skip)]
+    pub pomodoro: Option<Pomodoro>,
+    // Todo yanked with `y`, added as a blocker with `b`
+    #[serde(skip)]
+    pub blocker: Option<u64>,
+    // Set by changes that haven't been saved yet
+    #[serde(skip)]
+    pub dirty: bool,
+    #[serde(skip)]
+    pub message: Option<Message>,
+    // When the user last pressed a key or used the mouse, and when a save was last attempted
+    #[serde(skip)]
+    last_input: Option<Instant>,
+    #[serde(skip)]
+    last_save: Option<Instant>,
+    // The data file as of the last load or save, None if there was no file
+    #[serde(skip)]
+    pub disk: Option<config::FileStamp>,
+    // Pid of the instance holding the lock file, saving is off until it lets go
+    #[serde(skip)]
+    pub locked_by: Option<u32>,
+}
+
+// How long a message stays in the status line
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(4);
+
+// Feedback shown in the status line until it fades
+pub struct Message {
+    pub text: String,
+    pub error: bool,
+    pub shown_at: Instant,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
@@ -22,6 +91,13 @@ pub enum Mode {
     Normal,
     Visual,
     Command,
+    Tags,
+    Archive,
+    Timesheet,
+    // The data file changed on disk while there were unsaved changes
+    Conflict,
+    // The lock file was left behind by an instance that is gone
+    StaleLock,
 }
 
 impl fmt::Display for Mode {
@@ -31,6 +107,11 @@ impl fmt::Display for Mode {
             Mode::Normal => write!(f, "Normal Mode"),
             Mode::Visual => write!(f, "Visual Mode"),
             Mode::Command => write!(f, "Command Mode"),
+            Mode::Tags => write!(f, "Tag Browser"),
+            Mode::Archive => write!(f, "Archive"),
+            Mode::Timesheet => write!(f, "Timesheet"),
+            Mode::Conflict => write!(f, "Conflict"),
+            Mode::StaleLock => write!(f, "Stale Lock"),
         }
     }
 }
@@ -43,6 +124,25 @@ impl App {
             todolists: vec![TodoList::new()],
             todolist_idx: None,
             nominal_line_num: 0,
+            settings: Settings::default(),
+            todolist_offset: Cell::new(0),
+            tab_offset: Cell::new(0),
+            zen: false,
+            visible_todolists: RefCell::new(Vec::new()),
+            tab_areas: RefCell::new(Vec::new()),
+            drag_source: None,
+            filter: None,
+            tag_browser: TagBrowser::default(),
+            archive_view: ArchiveView::default(),
+            timesheet: Timesheet::default(),
+            pomodoro: None,
+            blocker: None,
+            dirty: false,
+            message: None,
+            last_input: None,
+            last_save: None,
+            disk: None,
+            locked_by: None,
         }
     }
 
@@ -57,6 +157,73 @@ impl App {
         return None;
     }
 
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.message = Some(Message { text: text.into(), error: false, shown_at: Instant::now() });
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.message = Some(Message { text: text.into(), error: true, shown_at: Instant::now() });
+    }
+
+    // Saves the todolists, returning false and showing why if it failed. Asks what
+    // to do instead if the data file was changed by something else
+    pub fn save(&mut self) -> bool {
+        if !self.relock() {
+            // Nothing is lost when there was nothing to save
+            return !self.dirty;
+        }
+        if self.changed_on_disk() {
+            self.open_conflict();
+            return false;
+        }
+        self.write()
+    }
+
+    // Saves over whatever is in the data file
+    fn write(&mut self) -> bool {
+        self.last_save = Some(Instant::now());
+        match config::save(self) {
+            Ok(count) => {
+                self.dirty = false;
+                self.disk = config::stamp();
+                self.info(format!("saved {} todos", count));
+                true
+            }
+            Err(e) => {
+                self.error(format!("save failed: {}", e));
+                false
+            }
+        }
+    }
+
+    // Leaves whatever mode the app is in so the saved state opens cleanly, then saves
+    // any unsaved changes
+    pub fn save_before_exit(&mut self) -> bool {
+        match self.mode {
+            Mode::Insert => self.toggle_editing(),
+            Mode::Visual => self.toggle_visual(),
+            Mode::Command => {
+                self.command_prompt.parse();
+                self.mode = Mode::Normal;
+                if let Some(idx) = self.todolist_idx {
+                    self.select_todolist(idx);
+                }
+            }
+            Mode::Normal | Mode::Tags | Mode::Archive | Mode::Timesheet | Mode::Conflict | Mode::StaleLock => {
+                self.mode = Mode::Normal;
+            }
+        }
+        if !self.dirty {
+            return true;
+        }
+        // There's no one left to ask about changes on disk or a lock held elsewhere,
+        // so ours are kept aside rather than saved over theirs
+        if !self.relock() || self.changed_on_disk() {
+            return config::save_copy(self).is_ok();
+        }
+        self.write()
+    }
+
     fn unselect_todolist(&mut self, idx: usize) {
         assert!(idx < self.todolists.len());
         self.todolists[idx].unselect(self.mode);
@@ -84,6 +251,7 @@ impl App {
             let pos = todolist.create_todo_below();
             todolist.select_todo(pos);
             self.toggle_editing();
+            self.dirty = true;
             return Some(pos);
         }
         return None;
@@ -96,9 +264,12 @@ impl App {
             pos = idx + 1;
             self.unselect_todolist(idx);
         }
-        self.todolists.push(TodoList::new());
+        let mut todolist = TodoList::new();
+        todolist.visibility = self.visibility();
+        self.todolists.push(todolist);
         self.move_todolist(self.todolists.len() - 1, pos);
         self.select_todolist(pos);
+        self.dirty = true;
         return pos
     }
 
@@ -126,6 +297,39 @@ impl App {
         }
     }
 
+    pub fn jump_to_todolist(&mut self, idx: usize) {
+        assert!(self.mode == Mode::Normal);
+        if let Some(current) = self.todolist_idx {
+            self.unselect_todolist(current);
+        }
+        self.select_todolist(idx);
+    }
+
+    pub fn next_todolist(&mut self) {
+        let len = self.todolists.len();
+        if len > 0 {
+            let idx = self.todolist_idx.map_or(0, |idx| (idx + 1) % len);
+            self.jump_to_todolist(idx);
+        }
+    }
+
+    pub fn prev_todolist(&mut self) {
+        let len = self.todolists.len();
+        if len > 0 {
+            let idx = self.todolist_idx.map_or(0, |idx| (idx + len - 1) % len);
+            self.jump_to_todolist(idx);
+        }
+    }
+
+    // Finds a todolist by its 1-based position or by the start of its title
+    fn find_todolist(&self, query: &str) -> Option<usize> {
+        if let Ok(num) = query.parse::<usize>() {
+            return (1..=self.todolists.len()).contains(&num).then(|| num - 1);
+        }
+        let query = query.to_lowercase();
+        self.todolists.iter().position(|todolist| todolist.title.to_lowercase().starts_with(&query))
+    }
+
     pub fn move_up(&mut self) {
         let mode = self.mode;
         if let Some(todolist) = self.current_todolist() {
@@ -142,6 +346,22 @@ impl App {
         }
     }
 
+    pub fn scroll_half_page_up(&mut self) {
+        let mode = self.mode;
+        if let Some(todolist) = self.current_todolist() {
+            todolist.scroll_half_page_up(mode);
+            self.nominal_line_num = todolist.todo_idx.unwrap_or(0);
+        }
+    }
+
+    pub fn scroll_half_page_down(&mut self) {
+        let mode = self.mode;
+        if let Some(todolist) = self.current_todolist() {
+            todolist.scroll_half_page_down(mode);
+            self.nominal_line_num = todolist.todo_idx.unwrap_or(0);
+        }
+    }
+
     ////////////////
     // Shuffling lists 
     ////////////////
@@ -166,6 +386,7 @@ impl App {
             if todolist_idx > 0 {
                 self.move_todolist(todolist_idx, todolist_idx - 1);
                 self.move_left();
+                self.dirty = true;
             }
         }
     }
@@ -175,6 +396,7 @@ impl App {
             if todolist_idx < self.todolists.len() - 1 {
                 self.move_todolist(todolist_idx, todolist_idx + 1);
                 self.move_right();
+                self.dirty = true;
             }
         }
     }
@@ -182,14 +404,18 @@ impl App {
     pub fn move_todo_up(&mut self) {
         let mode = self.mode;
         if let Some(todolist) = self.current_todolist() {
-            todolist.move_todo_up(mode);
+            if todolist.move_todo_up(mode) {
+                self.dirty = true;
+            }
         }
     }
 
     pub fn move_todo_down(&mut self) {
         let mode = self.mode;
         if let Some(todolist) = self.current_todolist() {
-            todolist.move_todo_down(mode);
+            if todolist.move_todo_down(mode) {
+                self.dirty = true;
+            }
         }
     }
 
@@ -201,6 +427,7 @@ impl App {
         if let Some(idx) = self.todolist_idx{
             self.todolists.remove(idx);
             self.select_todolist(idx);
+            self.dirty = true;
 
             let mode = self.mode;
             let line_num = self.nominal_line_num;
@@ -214,6 +441,7 @@ impl App {
         let mode = self.mode;
         if let Some(todolist) = self.current_todolist() {
             todolist.delete_todo(mode);
+            self.dirty = true;
         }
         self.mode = Mode::Normal;
     }
@@ -221,11 +449,802 @@ impl App {
     pub fn toggle_completed (&mut self) {
         let mode = self.mode;
         assert!(mode == Mode::Normal || mode == Mode::Visual);
+        let statuses = self.settings.statuses.clone();
+        let open_ids = self.open_ids();
+        if let Some(todolist) = self.current_todolist() {
+            let changes = todolist.toggle_completed(mode, &statuses, &open_ids);
+            self.apply_status_changes(changes);
+        }
+    }
+
+    pub fn cycle_status(&mut self) {
+        let mode = self.mode;
+        assert!(mode == Mode::Normal || mode == Mode::Visual);
+        let statuses = self.settings.statuses.clone();
+        let open_ids = self.open_ids();
+        if let Some(todolist) = self.current_todolist() {
+            let changes = todolist.cycle_status(mode, &statuses, &open_ids);
+            self.apply_status_changes(changes);
+        }
+    }
+
+    // Sets the status of the selected todo by name
+    pub fn set_status(&mut self, name: &str) -> Result<(), String> {
+        let Some(status) = status::find(&self.settings.statuses, name).cloned() else {
+            return Err(format!("unknown status: {}", name));
+        };
+        let open_ids = self.open_ids();
+        if let Some(todolist) = self.current_todolist() {
+            let changes = todolist.set_status(Mode::Normal, &status, &open_ids);
+            self.apply_status_changes(changes);
+        }
+        Ok(())
+    }
+
+    // Takes how many todos changed status and how many were refused for being blocked
+    fn apply_status_changes(&mut self, (changed, blocked): (usize, usize)) {
+        if changed > 0 {
+            self.unblock_finished();
+            self.dirty = true;
+        }
+        if blocked == 1 && self.mode == Mode::Normal {
+            let blocker = self.open_blocker().unwrap_or_default();
+            self.error(format!("blocked by \"{}\"", blocker));
+        } else if blocked > 0 {
+            self.error(format!("{} todos are blocked by open todos", blocked));
+        }
+    }
+
+    ////////////////
+    // Locking
+    ////////////////
+
+    // Takes the outcome of trying to lock the data file at startup
+    pub fn apply_lock(&mut self, lock: std::io::Result<config::Lock>) {
+        match lock {
+            Ok(config::Lock::Acquired) => {}
+            Ok(config::Lock::HeldBy { pid, stale }) => {
+                self.locked_by = Some(pid);
+                if stale {
+                    self.mode = Mode::StaleLock;
+                } else {
+                    self.error(format!("opened read-only, pid {} has the data file locked", pid));
+                }
+            }
+            Err(e) => self.error(format!("couldn't lock the data file: {}", e)),
+        }
+    }
+
+    // Tries again to take a lock another instance held, it may have quit since.
+    // One that quit without letting go of it is offered to be taken over
+    fn relock(&mut self) -> bool {
+        if self.locked_by.is_none() {
+            return true;
+        }
+        match config::lock() {
+            Ok(config::Lock::Acquired) => {
+                self.locked_by = None;
+                true
+            }
+            Ok(config::Lock::HeldBy { pid, stale }) => {
+                self.locked_by = Some(pid);
+                if stale && self.mode == Mode::Normal {
+                    self.mode = Mode::StaleLock;
+                } else {
+                    self.error(format!("E45: read-only, pid {} has the data file locked", pid));
+                }
+                false
+            }
+            Err(e) => {
+                self.error(format!("couldn't lock the data file: {}", e));
+                false
+            }
+        }
+    }
+
+    pub fn steal_lock(&mut self) {
+        assert!(self.mode == Mode::StaleLock);
+        self.mode = Mode::Normal;
+        match config::steal_lock() {
+            Ok(()) => {
+                self.locked_by = None;
+                self.info("took over the stale lock");
+            }
+            Err(e) => self.error(format!("couldn't take the lock: {}", e)),
+        }
+    }
+
+    pub fn open_read_only(&mut self) {
+        assert!(self.mode == Mode::StaleLock);
+        self.mode = Mode::Normal;
+    }
+
+    ////////////////
+    // External changes
+    ////////////////
+
+    // Whether something else wrote the data file since we last read or wrote it
+    fn changed_on_disk(&mut self) -> bool {
+        let Some(modified) = config::modified() else {
+            return false;
+        };
+        if self.disk.is_some_and(|known| known.modified == modified) {
+            return false;
+        }
+        let current = config::stamp();
+        if current.is_some() && current.map(|stamp| stamp.hash) == self.disk.map(|stamp| stamp.hash) {
+            // Touched but not changed
+            self.disk = current;
+            return false;
+        }
+        current.is_some()
+    }
+
+    // Picks up changes to the data file, right away if there is nothing unsaved
+    fn check_disk(&mut self) {
+        if self.mode != Mode::Normal || !self.changed_on_disk() {
+            return;
+        }
+        if self.dirty {
+            self.open_conflict();
+        } else {
+            self.reload();
+        }
+    }
+
+    fn open_conflict(&mut self) {
+        self.mode = Mode::Conflict;
+        self.error("the data file changed on disk");
+    }
+
+    // Replaces the todolists and settings with the ones in the data file
+    pub fn reload(&mut self) {
+        match config::reload() {
+            Ok(theirs) => {
+                self.todolists = theirs.todolists;
+                self.todolist_idx = theirs.todolist_idx;
+                self.nominal_line_num = theirs.nominal_line_num;
+                self.settings = theirs.settings;
+                self.disk = theirs.disk;
+                self.dirty = false;
+                self.mode = Mode::Normal;
+                self.sync_visibility();
+                self.info("reloaded, the data file changed on disk");
+            }
+            Err(e) => {
+                self.mode = Mode::Normal;
+                self.error(format!("reload failed: {}", e));
+            }
+        }
+    }
+
+    // Resolves a conflict by saving over the data file
+    pub fn keep_ours(&mut self) {
+        assert!(self.mode == Mode::Conflict);
+        self.mode = Mode::Normal;
+        // Their version is settled either way, a read-only instance can't save over it
+        // yet but shouldn't be asked again
+        self.disk = config::stamp();
+        if self.relock() {
+            self.write();
+        }
+    }
+
+    // Resolves a conflict by adding the todos only found on disk to ours, archived ones
+    // included, then saving. Todolists are matched by title, todos by id and creation time.
+    // Their edits to todos we also have are dropped and todos we deleted come back
+    pub fn merge_theirs(&mut self) {
+        assert!(self.mode == Mode::Conflict);
+        self.mode = Mode::Normal;
+        let theirs = match config::reload() {
+            Ok(theirs) => theirs,
+            Err(e) => {
+                self.error(format!("merge failed: {}", e));
+                return;
+            }
+        };
+        let ours: HashSet<(u64, Option<chrono::DateTime<chrono::Local>>)> = self.todolists.iter()
+            .flat_map(|todolist| todolist.todos().iter().chain(todolist.archive.iter().map(|archived| &archived.todo)))
+            .map(|todo| (todo.id, todo.created_at))
+            .collect();
+        let taken_ids: HashSet<u64> = ours.iter().map(|(id, _)| *id).collect();
+        let is_new = |todo: &todo::Todo| !ours.contains(&(todo.id, todo.created_at));
+        // Both sides hand out ids on their own
+        let adopt = |mut todo: todo::Todo| {
+            if taken_ids.contains(&todo.id) {
+                todo.id = todo::next_id();
+            }
+            todo.selected = false;
+            todo.editing = false;
+            todo
+        };
+        let mut added = 0;
+        for their_list in theirs.todolists {
+            // New subtasks stay under their new parent, the rest start at the top level
+            // rather than end up under whatever todo happens to be last
+            let mut new_todos = Vec::new();
+            let mut root = None;
+            for todo in their_list.todos() {
+                if root.is_some_and(|root| todo.depth <= root) {
+                    root = None;
+                }
+                if !is_new(todo) {
+                    continue;
+                }
+                let mut todo = todo.clone();
+                todo.depth = match root {
+                    Some(root) => todo.depth - root,
+                    None => {
+                        root = Some(todo.depth);
+                        0
+                    }
+                };
+                new_todos.push(todo);
+            }
+            let new_archived: Vec<ArchivedTodo> = their_list.archive.into_iter()
+                .filter(|archived| is_new(&archived.todo))
+                .collect();
+            if new_todos.is_empty() && new_archived.is_empty() {
+                continue;
+            }
+            let list_idx = match self.todolists.iter().position(|todolist| todolist.title == their_list.title) {
+                Some(idx) => idx,
+                None => {
+                    let mut todolist = TodoList::new();
+                    todolist.title = their_list.title.clone();
+                    todolist.visibility = self.visibility();
+                    self.todolists.push(todolist);
+                    self.todolists.len() - 1
+                }
+            };
+            let todolist = &mut self.todolists[list_idx];
+            for todo in new_todos {
+                let len = todolist.len();
+                todolist.insert_todo(len, adopt(todo));
+                added += 1;
+            }
+            for archived in new_archived {
+                todolist.archive.push(ArchivedTodo { todo: adopt(archived.todo), ..archived });
+                added += 1;
+            }
+        }
+        self.disk = theirs.disk;
+        self.dirty = true;
+        if !self.relock() {
+            self.info(format!("merged {} todos from disk, read-only so not saved", added));
+        } else if self.write() {
+            self.info(format!("merged {} todos from disk", added));
+        }
+    }
+
+    ////////////////
+    // Time tracking
+    ////////////////
+
+    // Todolist and todo index of the todo whose timer is running
+    fn running_timer(&self) -> Option<(usize, usize)> {
+        self.todolists.iter().enumerate().find_map(|(list_idx, todolist)| {
+            let todo_idx = todolist.todos().iter().position(|todo| todo.timer.is_some())?;
+            Some((list_idx, todo_idx))
+        })
+    }
+
+    // Starts a timer on the selected todo, stopping whichever timer was running,
+    // or stops the selected todo's timer
+    pub fn toggle_timer(&mut self) {
+        let selected = self.selected_todo_id();
+        if let Some((list_idx, todo_idx)) = self.running_timer() {
+            let todo = &mut self.todolists[list_idx].todos_mut()[todo_idx];
+            todo.stop_timer();
+            self.dirty = true;
+            if Some(todo.id) == selected {
+                return;
+            }
+        }
+        if let Some(todo) = self.current_todolist().and_then(|todolist| todolist.current_todo()) {
+            todo.start_timer();
+            self.dirty = true;
+        }
+    }
+
+    fn find_todo_mut(&mut self, id: u64) -> Option<&mut todo::Todo> {
+        self.todolists.iter_mut()
+            .flat_map(|todolist| todolist.todos_mut())
+            .find(|todo| todo.id == id)
+    }
+
+    // Starts pomodoros on the selected todo, or stops the running ones
+    pub fn toggle_pomodoro(&mut self) {
+        if self.pomodoro.take().is_some() {
+            return;
+        }
+        self.pomodoro = self.selected_todo_id().map(Pomodoro::new);
+    }
+
+    // Called on every turn of the event loop to let time based state move on
+    pub fn tick(&mut self) {
+        if self.message.as_ref().is_some_and(|message| message.shown_at.elapsed() > MESSAGE_TIMEOUT) {
+            self.message = None;
+        }
+        self.advance_pomodoro();
+        self.check_disk();
+        self.autosave();
+    }
+
+    pub fn record_input(&mut self) {
+        self.last_input = Some(Instant::now());
+    }
+
+    // Saves unsaved changes every `autosave` seconds and after `autosaveidle` seconds
+    // without input. Only in normal mode, so a todo being edited isn't saved half done
+    fn autosave(&mut self) {
+        let now = Instant::now();
+        let last_save = *self.last_save.get_or_insert(now);
+        let last_input = *self.last_input.get_or_insert(now);
+        if !self.dirty || self.mode != Mode::Normal || self.locked_by.is_some() {
+            return;
+        }
+        let elapsed = |seconds: u64, since: Instant| seconds > 0 && now - since >= Duration::from_secs(seconds);
+        let interval = elapsed(self.settings.autosave, last_save);
+        // Failed saves are retried at the idle pace rather than on every tick
+        let idle = elapsed(self.settings.autosaveidle, last_input) && elapsed(self.settings.autosaveidle, last_save);
+        if interval || idle {
+            self.save();
+        }
+    }
+
+    fn advance_pomodoro(&mut self) {
+        let Some(pomodoro) = self.pomodoro.as_mut() else {
+            return;
+        };
+        let now = chrono::Local::now();
+        let Some(ended) = pomodoro.advance(&self.settings, now) else {
+            return;
+        };
+        let (todo_id, phase) = (pomodoro.todo_id, pomodoro.phase);
+        match self.find_todo_mut(todo_id) {
+            Some(todo) => {
+                if ended == Phase::Work {
+                    todo.pomodoros.push(now);
+                    todo.touch();
+                    self.dirty = true;
+                }
+            }
+            // The todo was deleted while the pomodoro ran
+            None => {
+                self.pomodoro = None;
+                return;
+            }
+        }
+        pomodoro::notify(&self.settings.pomodorocmd, phase);
+    }
+
+    pub fn close_timesheet(&mut self) {
+        assert!(self.mode == Mode::Timesheet);
+        self.mode = Mode::Normal;
+    }
+
+    ////////////////
+    // Dependencies
+    ////////////////
+
+    // Gives every todo saved without an id one, and makes sure new ids don't collide
+    pub fn assign_ids(&mut self) {
+        let max_id = self.todolists.iter()
+            .flat_map(|todolist| todolist.todos().iter().map(|todo| todo.id)
+                .chain(todolist.archive.iter().map(|archived| archived.todo.id)))
+            .max()
+            .unwrap_or(0);
+        todo::reserve_ids(max_id);
+        for todolist in &mut self.todolists {
+            let archived = todolist.archive.iter_mut().map(|archived| &mut archived.todo);
+            for todo in archived {
+                if todo.id == 0 {
+                    todo.id = todo::next_id();
+                }
+            }
+            for todo in todolist.todos_mut() {
+                if todo.id == 0 {
+                    todo.id = todo::next_id();
+                }
+            }
+        }
+    }
+
+    // Description of the first open todo blocking the selected one
+    fn open_blocker(&self) -> Option<String> {
+        let todolist = &self.todolists[self.todolist_idx?];
+        let blocked_by = &todolist.selected_todo()?.blocked_by;
+        self.todolists.iter()
+            .flat_map(|todolist| todolist.todos())
+            .find(|todo| !todo.completed && blocked_by.contains(&todo.id))
+            .map(|todo| todo.description.clone())
+    }
+
+    pub fn open_ids(&self) -> HashSet<u64> {
+        self.todolists.iter()
+            .flat_map(|todolist| todolist.todos())
+            .filter(|todo| !todo.completed)
+            .map(|todo| todo.id)
+            .collect()
+    }
+
+    fn selected_todo_id(&self) -> Option<u64> {
+        let todolist = &self.todolists[self.todolist_idx?];
+        todolist.selected_todo().map(|todo| todo.id)
+    }
+
+    // Remembers the selected todo as the blocker for the next `add_blocker`
+    pub fn yank_blocker(&mut self) {
+        self.blocker = self.selected_todo_id();
+    }
+
+    // Marks the selected todo as blocked by the yanked todo
+    pub fn add_blocker(&mut self) {
+        let Some(blocker) = self.blocker else {
+            return;
+        };
+        if self.selected_todo_id() == Some(blocker) {
+            return;
+        }
+        if let Some(todo) = self.current_todolist().and_then(|todolist| todolist.current_todo()) {
+            if !todo.blocked_by.contains(&blocker) {
+                todo.blocked_by.push(blocker);
+                todo.touch();
+                self.dirty = true;
+            }
+        }
+    }
+
+    pub fn clear_blockers(&mut self) {
+        if let Some(todo) = self.current_todolist().and_then(|todolist| todolist.current_todo()) {
+            todo.blocked_by.clear();
+            todo.touch();
+            self.dirty = true;
+        }
+    }
+
+    // Drops links to todos that are finished or no longer exist
+    fn unblock_finished(&mut self) {
+        let open_ids = self.open_ids();
+        for todolist in &mut self.todolists {
+            for todo in todolist.todos_mut() {
+                todo.blocked_by.retain(|id| open_ids.contains(id));
+            }
+        }
+    }
+
+    pub fn toggle_zen(&mut self) {
+        self.zen ^= true;
+    }
+
+    ////////////////
+    // Mouse
+    ////////////////
+
+    fn todolist_at(&self, column: u16, row: u16) -> Option<(usize, ListHit)> {
+        self.visible_todolists.borrow().iter().find_map(|&idx| {
+            self.todolists[idx].hit_test(column, row).map(|hit| (idx, hit))
+        })
+    }
+
+    fn tab_at(&self, column: u16, row: u16) -> Option<usize> {
+        let position = Position::new(column, row);
+        self.tab_areas.borrow().iter().find(|(_, area)| area.contains(position)).map(|(idx, _)| *idx)
+    }
+
+    pub fn click(&mut self, column: u16, row: u16) {
+        self.drag_source = None;
+        if self.mode != Mode::Normal {
+            return;
+        }
+        if let Some(idx) = self.tab_at(column, row) {
+            self.jump_to_todolist(idx);
+            return;
+        }
+        if let Some((idx, hit)) = self.todolist_at(column, row) {
+            self.jump_to_todolist(idx);
+            let todolist = &mut self.todolists[idx];
+            if let Some(todo_idx) = todolist.todo_idx {
+                todolist.unselect_todo(todo_idx);
+            }
+            match hit {
+                ListHit::Title => {
+                    todolist.todo_idx = None;
+                }
+                ListHit::Todo(todo_idx) => {
+                    todolist.select_todo(todo_idx);
+                    self.drag_source = Some((idx, todo_idx));
+                }
+                ListHit::Checkbox(todo_idx) => {
+                    todolist.select_todo(todo_idx);
+                    self.toggle_completed();
+                }
+                ListHit::Below => {
+                    let len = todolist.len();
+                    todolist.select_todo(len.saturating_sub(1));
+                }
+            }
+            self.nominal_line_num = self.todolists[idx].todo_idx.unwrap_or(0);
+        }
+    }
+
+    // Drops a todo picked up by `click` onto whatever is under the mouse
+    pub fn release(&mut self, column: u16, row: u16) {
+        let Some((from_list, from_todo)) = self.drag_source.take() else {
+            return;
+        };
+        let Some((to_list, hit)) = self.todolist_at(column, row) else {
+            return;
+        };
+        if self.mode != Mode::Normal {
+            return;
+        }
+        let to_todo = match hit {
+            ListHit::Title => 0,
+            ListHit::Todo(idx) | ListHit::Checkbox(idx) => idx,
+            ListHit::Below => self.todolists[to_list].len(),
+        };
+        // A todo can't be dropped into its own subtasks
+        let end = self.todolists[from_list].subtree_end(from_todo);
+        if from_list == to_list && (from_todo..end).contains(&to_todo) {
+            return;
+        }
+        // It becomes a sibling of the todo it's dropped on
+        let len = self.todolists[to_list].len();
+        let depth = self.todolists[to_list].todos().get(to_todo).map_or(0, |todo| todo.depth);
+
+        self.unselect_todolist(from_list);
+        let todos = self.todolists[from_list].remove_subtree(from_todo);
+        let moved = todos.len();
+        let todolist = &mut self.todolists[to_list];
+        let pos = if from_list == to_list && to_todo >= end && to_todo < len {
+            // Moving down lands below the todo dropped on and its subtasks
+            todolist.subtree_end(to_todo - moved)
+        } else if from_list == to_list && to_todo >= end {
+            to_todo - moved
+        } else {
+            to_todo
+        };
+        let pos = todolist.insert_subtree(pos, depth, todos);
+        self.nominal_line_num = pos;
+        self.select_todolist(to_list);
+        self.dirty = true;
+    }
+
+    // Scrolling the wheel over a todolist moves its selection
+    pub fn scroll(&mut self, column: u16, row: u16, down: bool) {
+        if self.mode != Mode::Normal {
+            return;
+        }
+        if let Some((idx, _)) = self.todolist_at(column, row) {
+            if self.todolist_idx != Some(idx) {
+                self.jump_to_todolist(idx);
+            }
+            if down {
+                self.move_down();
+            } else {
+                self.move_up();
+            }
+        }
+    }
+
+    // Sets the due date of the selected todo, an empty date clears it
+    pub fn set_due(&mut self, date: &str) -> Result<(), String> {
+        let due = if date.is_empty() {
+            None
+        } else {
+            Some(Due::parse(date)?)
+        };
+        if let Some(todolist) = self.current_todolist() {
+            todolist.set_due(due);
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    // An empty estimate clears it
+    pub fn set_estimate(&mut self, input: &str) -> Result<(), String> {
+        let estimate = if input.is_empty() {
+            None
+        } else {
+            Some(Estimate::parse(input)?)
+        };
+        if let Some(todolist) = self.current_todolist() {
+            todolist.set_estimate(estimate);
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    pub fn indent(&mut self) {
+        let mode = self.mode;
+        if let Some(todolist) = self.current_todolist() {
+            todolist.indent(mode);
+            self.dirty = true;
+        }
+    }
+
+    pub fn outdent(&mut self) {
+        let mode = self.mode;
+        if let Some(todolist) = self.current_todolist() {
+            todolist.outdent(mode);
+            self.dirty = true;
+        }
+    }
+
+    pub fn toggle_collapsed(&mut self) {
+        let mode = self.mode;
         if let Some(todolist) = self.current_todolist() {
-            todolist.toggle_completed(mode);
+            if todolist.toggle_collapsed(mode) {
+                self.dirty = true;
+            }
         }
     }
 
+    pub fn raise_priority(&mut self) {
+        let mode = self.mode;
+        if let Some(todolist) = self.current_todolist() {
+            todolist.raise_priority(mode);
+            self.dirty = true;
+        }
+    }
+
+    pub fn lower_priority(&mut self) {
+        let mode = self.mode;
+        if let Some(todolist) = self.current_todolist() {
+            todolist.lower_priority(mode);
+            self.dirty = true;
+        }
+    }
+
+    // Reorders the current todolist, highest priority, soonest due or oldest timestamp first
+    pub fn sort(&mut self, key: &str) -> Result<(), String> {
+        let compare: fn(&todo::Todo, &todo::Todo) -> std::cmp::Ordering = match key {
+            "priority" | "" => |a, b| b.priority.cmp(&a.priority),
+            "due" => |a, b| {
+                match (a.due, b.due) {
+                    (Some(a), Some(b)) => a.deadline().cmp(&b.deadline()),
+                    (a, b) => b.is_some().cmp(&a.is_some()),
+                }
+            },
+            // Oldest first, todos without timestamps go last
+            "created" => |a, b| oldest_first(a.created_at, b.created_at),
+            "updated" => |a, b| oldest_first(a.updated_at, b.updated_at),
+            "completed" => |a, b| oldest_first(a.completed_at, b.completed_at),
+            _ => return Err(format!("unknown sort key: {}", key)),
+        };
+        let Some(todolist) = self.current_todolist() else {
+            return Ok(());
+        };
+        let before: Vec<u64> = todolist.todos().iter().map(|todo| todo.id).collect();
+        todolist.sort_by(compare);
+        let changed = todolist.todos().iter().map(|todo| todo.id).ne(before);
+        self.nominal_line_num = self.current_todolist().and_then(|todolist| todolist.todo_idx).unwrap_or(0);
+        if changed {
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    // Makes the selected todo repeat, an empty rule stops it repeating
+    pub fn set_recurrence(&mut self, rule: &str) -> Result<(), String> {
+        let recurrence = if rule.is_empty() {
+            None
+        } else {
+            Some(Recurrence::parse(rule)?)
+        };
+        if let Some(todolist) = self.current_todolist() {
+            todolist.set_recurrence(recurrence);
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    ////////////////
+    // Visibility
+    ////////////////
+
+    fn visibility(&self) -> Visibility {
+        Visibility {
+            tag: self.filter.clone(),
+            hide_completed: self.settings.hidecompleted,
+        }
+    }
+
+    // Pushes the app wide visibility options down to every todolist,
+    // needs to run whenever the filter or settings change
+    pub fn sync_visibility(&mut self) {
+        let visibility = self.visibility();
+        for todolist in &mut self.todolists {
+            todolist.visibility = visibility.clone();
+        }
+    }
+
+    pub fn toggle_hide_completed(&mut self) {
+        let mode = self.mode;
+        if let Some(todolist) = self.current_todolist() {
+            todolist.toggle_hide_completed(mode);
+            self.dirty = true;
+        }
+    }
+
+    ////////////////
+    // Tags
+    ////////////////
+
+    // Hides todos without the tag in every todolist, `None` shows everything again
+    pub fn set_filter(&mut self, tag: Option<String>) {
+        self.filter = tag;
+        self.sync_visibility();
+    }
+
+    pub fn open_tag_browser(&mut self) {
+        assert!(self.mode == Mode::Normal);
+        self.tag_browser = TagBrowser::new(&self.todolists);
+        self.mode = Mode::Tags;
+    }
+
+    pub fn close_tag_browser(&mut self) {
+        assert!(self.mode == Mode::Tags);
+        self.mode = Mode::Normal;
+    }
+
+    // Filters by the tag under the cursor in the tag browser
+    pub fn apply_tag_browser(&mut self) {
+        assert!(self.mode == Mode::Tags);
+        self.mode = Mode::Normal;
+        let tag = self.tag_browser.selected_tag().map(String::from);
+        if tag.is_none() {
+            return;
+        }
+        self.set_filter(tag);
+        // The selected todo may have just been hidden
+        if let Some(idx) = self.todolist_idx {
+            self.unselect_todolist(idx);
+            self.select_todolist(idx);
+        }
+    }
+
+    ////////////////
+    // Archive
+    ////////////////
+
+    pub fn close_archive(&mut self) {
+        assert!(self.mode == Mode::Archive);
+        self.mode = Mode::Normal;
+    }
+
+    // Moves the archived todo under the cursor back into its todolist
+    pub fn restore_archived(&mut self) {
+        assert!(self.mode == Mode::Archive);
+        if let Some((list_idx, archive_idx)) = self.archive_view.selected() {
+            self.todolists[list_idx].restore_archived(archive_idx);
+            self.archive_view.refresh(&self.todolists);
+            self.dirty = true;
+        }
+    }
+
+    pub fn start_archive_search(&mut self) {
+        self.archive_view.searching = true;
+        self.archive_view.query.clear();
+        self.archive_view.refresh(&self.todolists);
+    }
+
+    pub fn stop_archive_search(&mut self) {
+        self.archive_view.searching = false;
+    }
+
+    pub fn archive_search_char(&mut self, c: char) {
+        self.archive_view.query.push(c);
+        self.archive_view.refresh(&self.todolists);
+    }
+
+    pub fn archive_search_backspace(&mut self) {
+        self.archive_view.query.pop();
+        self.archive_view.refresh(&self.todolists);
+    }
+
     ////////////////
     // Commands
     ////////////////
@@ -237,7 +1256,14 @@ impl App {
         // the exit command mode state
         let mut should_save: bool = false;
         let mut should_exit: bool = false;
-        if let Some(cmd) = self.command_prompt.parse() {
+        let mut next_mode = Mode::Normal;
+        let mut result = Ok(());
+        let input = self.command_prompt.value.trim().to_string();
+        let cmd = self.command_prompt.parse();
+        if cmd.is_none() && input != ":" {
+            result = Err(format!("E492: Not an editor command: {}", input.trim_start_matches(':')));
+        }
+        if let Some(cmd) = cmd {
             match cmd {
                 Command::Clean => {
                     self.clean();
@@ -246,28 +1272,109 @@ impl App {
                     should_save = true;
                 }
                 Command::Quit => {
+                    if self.dirty {
+                        result = Err(String::from("E37: No write since last change (add ! to override)"));
+                    } else {
+                        should_exit = true;
+                    }
+                }
+                Command::ForceQuit => {
                     should_exit = true;
                 }
                 Command::SaveAndQuit => {
                     should_save = true;
                     should_exit = true;
                 }
+                Command::Exit => {
+                    should_save = self.dirty;
+                    should_exit = true;
+                }
+                Command::Set(assignment) => {
+                    result = self.settings.set(&assignment);
+                    self.sync_visibility();
+                    self.dirty |= result.is_ok();
+                }
+                Command::Due(date) => {
+                    result = self.set_due(&date);
+                }
+                Command::Estimate(estimate) => {
+                    result = self.set_estimate(&estimate);
+                }
+                Command::Every(rule) => {
+                    result = self.set_recurrence(&rule);
+                }
+                Command::Sort(key) => {
+                    result = self.sort(&key);
+                }
+                Command::Filter(tag) => {
+                    let tag = tag.trim_start_matches('#');
+                    self.set_filter((!tag.is_empty()).then(|| tag.to_string()));
+                }
+                Command::Tags => {
+                    self.tag_browser = TagBrowser::new(&self.todolists);
+                    next_mode = Mode::Tags;
+                }
+                Command::Archive => {
+                    self.archive_view = ArchiveView::default();
+                    self.archive_view.refresh(&self.todolists);
+                    next_mode = Mode::Archive;
+                }
+                Command::Pomodoro => {
+                    self.toggle_pomodoro();
+                }
+                Command::Timesheet => {
+                    self.timesheet = Timesheet::new(&self.todolists);
+                    next_mode = Mode::Timesheet;
+                }
+                Command::Purge => {
+                    self.purge_archive();
+                }
+                Command::Status(name) => {
+                    result = self.set_status(&name);
+                }
+                Command::Zen => {
+                    self.toggle_zen();
+                }
+                Command::Tab(query) => {
+                    match self.find_todolist(&query) {
+                        Some(idx) => self.todolist_idx = Some(idx),
+                        None => result = Err(format!("no todolist matches {}", query)),
+                    }
+                }
             }
         }
         self.mode = Mode::Normal;
         if let Some(idx) = self.todolist_idx {
             self.select_todolist(idx);
         }
-        if should_save {
-            config::save(self);
+        self.mode = next_mode;
+        if let Err(e) = result {
+            self.error(e);
+        }
+        if should_save && !self.save() {
+            return false;
         }
         return should_exit;
     }
 
     pub fn clean(&mut self) {
         let mode = self.mode;
+        let mut archived = 0;
+        for todolist in &mut self.todolists {
+            archived += todolist.archive_completed_todos(mode);
+        }
+        if archived > 0 {
+            self.dirty = true;
+        }
+    }
+
+    pub fn purge_archive(&mut self) {
+        let mut purged = 0;
         for todolist in &mut self.todolists {
-            todolist.delete_completed_todos(mode);
+            purged += todolist.purge_archive();
+        }
+        if purged > 0 {
+            self.dirty = true;
         }
     }
 
@@ -322,6 +1429,7 @@ impl App {
         assert!(mode == Mode::Insert);
         let todolist = self.current_todolist().expect("A todolist must be selected if in Insert mode");
         todolist.insert_backspace(mode);
+        self.dirty = true;
     }
 
     pub fn insert_char(&mut self, c: char) {
@@ -329,6 +1437,7 @@ impl App {
         assert!(mode == Mode::Insert);
         let todolist = self.current_todolist().expect("A todolist must be selected if in Insert mode");
         todolist.insert_char(c, mode);
+        self.dirty = true;
     }
 
     ////////////////
@@ -351,26 +1460,279 @@ impl App {
             },
             Mode::Insert => {},
             Mode::Command => {},
+            Mode::Tags => {},
+            Mode::Archive => {},
+            Mode::Timesheet => {},
+            Mode::Conflict => {},
+            Mode::StaleLock => {},
         }
     }
 }
 
+fn oldest_first<T: Ord>(a: Option<T>, b: Option<T>) -> std::cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    }
+}
+
 use ratatui::prelude::*;
 
+impl App {
+    // One tab per todolist with its open and total todo counts
+    fn tabs(&self) -> Vec<Span<'_>> {
+        self.todolists.iter().enumerate().map(|(i, todolist)| {
+            let (open, total) = todolist.counts();
+            let tab = Span::raw(format!(" {} {}/{} ", todolist.title, open, total));
+            if self.todolist_idx == Some(i) {
+                tab.black().on_yellow()
+            } else {
+                tab
+            }
+        }).collect()
+    }
+
+    // Draws the tabs centered on one row, scrolling them like the todolists when they
+    // don't all fit, and remembers where each one went so they can be clicked
+    fn render_tab_bar(&self, area: Rect, buf: &mut Buffer) {
+        let tabs = self.tabs();
+        let widths: Vec<u16> = tabs.iter().map(|tab| tab.width() as u16).collect();
+        // Width of the tabs in `range` with a separator between each
+        let span = |range: std::ops::Range<usize>| {
+            let count = range.len() as u16;
+            widths[range].iter().sum::<u16>() + count.saturating_sub(1)
+        };
+        let len = tabs.len();
+        let overflowing = span(0..len) > area.width;
+        // Room for the scroll markers on both sides
+        let available = if overflowing { area.width.saturating_sub(4) } else { area.width };
+        let mut offset = min(self.tab_offset.get(), len.saturating_sub(1));
+        if !overflowing {
+            offset = 0;
+        }
+        if let Some(idx) = self.todolist_idx {
+            offset = min(offset, idx);
+            while offset < idx && span(offset..idx + 1) > available {
+                offset += 1;
+            }
+        }
+        // Don't leave room unused on the right while tabs are hidden on the left
+        while offset > 0 && span(offset - 1..len) <= available {
+            offset -= 1;
+        }
+        let mut end = offset;
+        while end < len && span(offset..end + 1) <= available {
+            end += 1;
+        }
+        // A single tab too wide for the bar is still shown, cut off
+        end = max(end, min(offset + 1, len));
+        self.tab_offset.set(offset);
+
+        let mut spans = Vec::new();
+        if overflowing {
+            spans.push(Span::raw(if offset > 0 { "< " } else { "  " }));
+        }
+        let mut ranges = Vec::new();
+        let mut x = if overflowing { 2 } else { 0 };
+        for (i, tab) in tabs.into_iter().enumerate().take(end).skip(offset) {
+            if i > offset {
+                spans.push(Span::raw("│"));
+                x += 1;
+            }
+            ranges.push((i, x, widths[i]));
+            x += widths[i];
+            spans.push(tab);
+        }
+        if overflowing {
+            spans.push(Span::raw(if end < len { " >" } else { "  " }));
+        }
+        let line = Line::from(spans);
+        let width = min(line.width() as u16, area.width);
+        let left = area.x + (area.width - width) / 2;
+        line.render(Rect::new(left, area.y, width, 1), buf);
+
+        let mut tab_areas = self.tab_areas.borrow_mut();
+        tab_areas.clear();
+        for (idx, x, width) in ranges {
+            let tab = Rect::new(left + x, area.y, width, 1).intersection(area);
+            if !tab.is_empty() {
+                tab_areas.push((idx, tab));
+            }
+        }
+    }
+
+    fn render_todolists(&self, body: Rect, buf: &mut Buffer) {
+        // Only as many lists as fit at the minimum width are drawn, scrolling
+        // horizontally so the selected list is always one of them
+        let len = self.todolists.len();
+        let fit = max(body.width / max(self.settings.listwidth, 1), 1) as usize;
+        let visible = min(fit, len);
+        let mut offset = self.todolist_offset.get();
+        if let Some(idx) = self.todolist_idx {
+            if idx < offset {
+                offset = idx;
+            }
+            else if idx >= offset + visible {
+                offset = idx + 1 - visible;
+            }
+        }
+        offset = min(offset, len - visible);
+        self.todolist_offset.set(offset);
+
+        let hidden_left = offset;
+        let hidden_right = len - offset - visible;
+        let body_panes = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Fill(1),
+                Constraint::Length(if hidden_left + hidden_right > 0 { 1 } else { 0 }),
+            ])
+            .split(body);
+
+        let todolist_constraints: Vec<Constraint> = vec![Constraint::Max(40); visible];
+        let todolist_panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(todolist_constraints)
+            .split(body_panes[0]);
+        *self.visible_todolists.borrow_mut() = (offset..offset + visible).collect();
+        let open_ids = self.open_ids();
+        for (pane, todolist) in todolist_panes.iter().zip(&self.todolists[offset..offset + visible]) {
+            let view = TodoListView { todolist, settings: &self.settings, open_ids: &open_ids };
+            view.render(*pane, buf);
+        }
+
+        // Scroll indicators
+        if hidden_left > 0 {
+            Line::from(format!("< {} more", hidden_left))
+                .left_aligned()
+                .render(body_panes[1], buf);
+        }
+        if hidden_right > 0 {
+            Line::from(format!("{} more >", hidden_right))
+                .right_aligned()
+                .render(body_panes[1], buf);
+        }
+    }
+
+    fn render_conflict(&self, area: Rect, buf: &mut Buffer) {
+        let popup = area.centered(Constraint::Max(60), Constraint::Length(10));
+        let prompt = Paragraph::new(vec![
+            Line::from(format!("{} was changed by something else", config::display_path())),
+            Line::from("while you have unsaved changes."),
+            Line::raw(""),
+            Line::from("r: reload it, dropping your changes").dark_gray(),
+            Line::from("k: keep yours, saving over it").dark_gray(),
+            Line::from("m: merge, adding its new todos to yours").dark_gray(),
+            Line::from("   its edits to your todos are lost and").dark_gray(),
+            Line::from("   todos you deleted come back").dark_gray(),
+        ])
+            .centered()
+            .block(Block::bordered().title(Line::from(" Conflict ").centered()).border_style(Color::Red));
+        Clear.render(popup, buf);
+        prompt.render(popup, buf);
+    }
+
+    fn render_stale_lock(&self, area: Rect, buf: &mut Buffer) {
+        let popup = area.centered(Constraint::Max(60), Constraint::Length(7));
+        let pid = self.locked_by.unwrap_or(0);
+        let prompt = Paragraph::new(vec![
+            Line::from(format!("The data file is locked by pid {},", pid)),
+            Line::from("which is no longer running."),
+            Line::raw(""),
+            Line::from("s: take over the lock").dark_gray(),
+            Line::from("r: open read-only").dark_gray(),
+        ])
+            .centered()
+            .block(Block::bordered().title(Line::from(" Stale Lock ").centered()).border_style(Color::Red));
+        Clear.render(popup, buf);
+        prompt.render(popup, buf);
+    }
+
+    // Vim like status line: list, position, data file, unsaved flag, open todos and the latest message
+    fn render_status_line(&self, area: Rect, buf: &mut Buffer) {
+        let mut left = Vec::new();
+        if let Some(todolist) = self.todolist_idx.map(|idx| &self.todolists[idx]) {
+            let position = match todolist.todo_idx {
+                Some(idx) => (idx + 1).to_string(),
+                None => String::from("-"),
+            };
+            left.push(Span::raw(format!(" {} {}/{} ", todolist.title, position, todolist.len())).black().on_yellow());
+        }
+        // The flag goes first so a long path can't push it out of view
+        if self.dirty {
+            left.push(Span::raw(" [+]").bold());
+        }
+        if self.locked_by.is_some() {
+            left.push(Span::raw(" [RO]").red().bold());
+        }
+        left.push(Span::raw(format!(" {}", config::display_path())));
+
+        let open: usize = self.todolists.iter().map(|todolist| todolist.counts().0).sum();
+        let mut right = Vec::new();
+        if let Some(message) = &self.message {
+            let text = Span::raw(format!(" {} ", message.text));
+            right.push(if message.error { text.red() } else { text.green() });
+        }
+        right.push(Span::raw(format!("│ {} open ", open)));
+
+        // The message wins over the path when space runs out
+        let right = Line::from(right);
+        let [left_area, right_area] = Layout::horizontal([
+            Constraint::Fill(1),
+            Constraint::Length(right.width() as u16),
+        ]).areas(area);
+        Line::from(left).render(left_area, buf);
+        right.render(right_area, buf);
+    }
+
+    // The selected todolist gets the whole body with the selected todo's details beneath it
+    fn render_zen(&self, idx: usize, body: Rect, buf: &mut Buffer) {
+        let todolist = &self.todolists[idx];
+        *self.visible_todolists.borrow_mut() = vec![idx];
+        let zen_panes = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Fill(1),
+                Constraint::Max(8),
+            ])
+            .split(body);
+        let open_ids = self.open_ids();
+        let view = TodoListView { todolist, settings: &self.settings, open_ids: &open_ids };
+        view.render(zen_panes[0], buf);
+        match todolist.selected_todo() {
+            Some(todo) => TodoDetail { todo, settings: &self.settings }.render(zen_panes[1], buf),
+            None => Block::bordered().title(" Details ").render(zen_panes[1], buf),
+        }
+    }
+}
+
 impl Widget for &App {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let app_panes = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
-                Constraint::Max(4),
+                Constraint::Max(5),
                 Constraint::Fill(1),
+                Constraint::Length(1),
                 Constraint::Max(3),
             ])
             .split(area);
 
         // Header
         let title = "Todolist Manager";
-        let mode_text = self.mode.to_string();
+        let mut mode_text = match &self.filter {
+            Some(tag) => format!("{} | filter #{}", self.mode, tag),
+            None => self.mode.to_string(),
+        };
+        if let Some((list_idx, todo_idx)) = self.running_timer() {
+            let todo = &self.todolists[list_idx].todos()[todo_idx];
+            let elapsed = todo.timer.map(|start| chrono::Local::now() - start).unwrap_or_default();
+            mode_text.push_str(&format!(" | ⏱ {} {}", todo.value, timesheet::format_clock(elapsed)));
+        }
+        if let Some(pomodoro) = &self.pomodoro {
+            let remaining = pomodoro.remaining(&self.settings, chrono::Local::now()).num_seconds().max(0);
+            mode_text.push_str(&format!(" | 🍅 {} {:02}:{:02} ({} done)", pomodoro.phase, remaining / 60, remaining % 60, pomodoro.cycles));
+        }
         let header = Paragraph::new(vec![
             Line::from(title), 
             Line::from(mode_text),
@@ -378,19 +1740,238 @@ impl Widget for &App {
             .centered()
             .block(Block::bordered());
         header.render(app_panes[0], buf);
+        let header_inner = app_panes[0].inner(Margin::new(1, 1));
+        if header_inner.height > 2 {
+            let tab_row = Rect { y: header_inner.y + 2, height: 1, ..header_inner };
+            self.render_tab_bar(tab_row, buf);
+        } else {
+            self.tab_areas.borrow_mut().clear();
+        }
 
         // Todolists
-        let len = self.todolists.len();
-        let todolist_constraints: Vec<Constraint> = vec![Constraint::Max(40); len];
-        let todolist_panes = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints(todolist_constraints)
-            .split(app_panes[1]);
-        for (pane, todolist) in todolist_panes.iter().zip(&self.todolists) {
-            todolist.render(*pane, buf);
+        match self.todolist_idx {
+            Some(idx) if self.zen => self.render_zen(idx, app_panes[1], buf),
+            _ => self.render_todolists(app_panes[1], buf),
         }
 
+        self.render_status_line(app_panes[2], buf);
+
         // Command Prompt
-        self.command_prompt.render(app_panes[2], buf);
+        self.command_prompt.render(app_panes[3], buf);
+
+        if self.mode == Mode::Tags {
+            self.tag_browser.render(area, buf);
+        }
+        if self.mode == Mode::Archive {
+            let archive = ArchiveViewWidget { view: &self.archive_view, todolists: &self.todolists };
+            archive.render(area, buf);
+        }
+        if self.mode == Mode::Timesheet {
+            self.timesheet.render(area, buf);
+        }
+        if self.mode == Mode::Conflict {
+            self.render_conflict(area, buf);
+        }
+        if self.mode == Mode::StaleLock {
+            self.render_stale_lock(area, buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Far above any real pid limit, so never running
+    const GONE: u32 = i32::MAX as u32;
+
+    fn hold_lock(pid: u32) {
+        let path = config::data_path().with_file_name("todos.json.lock");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, pid.to_string()).unwrap();
+    }
+
+    fn release_lock() {
+        fs::remove_file(config::data_path().with_file_name("todos.json.lock")).unwrap();
+    }
+
+    fn edited_app() -> App {
+        let mut app = App::new();
+        app.create_todolist();
+        app.dirty = true;
+        app
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_lock_is_offered_and_taken_over() {
+        let _home = config::temp_home();
+        hold_lock(GONE);
+        let mut app = edited_app();
+        app.apply_lock(config::lock());
+        assert_eq!(app.mode, Mode::StaleLock);
+        app.steal_lock();
+        assert_eq!(app.mode, Mode::Normal);
+        assert_eq!(app.locked_by, None);
+        assert!(app.save());
+        assert!(config::data_path().exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn live_lock_opens_read_only_until_released() {
+        let _home = config::temp_home();
+        let parent = std::os::unix::process::parent_id();
+        hold_lock(parent);
+        let mut app = edited_app();
+        app.apply_lock(config::lock());
+        assert_eq!(app.mode, Mode::Normal);
+        assert_eq!(app.locked_by, Some(parent));
+        assert!(!app.save());
+        assert!(app.dirty);
+        assert!(!config::data_path().exists());
+
+        release_lock();
+        assert!(app.save());
+        assert_eq!(app.locked_by, None);
+        assert!(!app.dirty);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lock_going_stale_mid_session_is_offered() {
+        let _home = config::temp_home();
+        let parent = std::os::unix::process::parent_id();
+        hold_lock(parent);
+        let mut app = edited_app();
+        app.apply_lock(config::lock());
+        hold_lock(GONE);
+        assert!(!app.save());
+        assert_eq!(app.mode, Mode::StaleLock);
+        assert_eq!(app.locked_by, Some(GONE));
+    }
+
+    // An app with one todolist holding a todo per entry, `(text, depth)`
+    fn app_with(todos: &[(&str, usize)]) -> App {
+        let mut app = App::new();
+        let mut todolist = TodoList::new();
+        for (i, (value, depth)) in todos.iter().enumerate() {
+            let mut todo = todo::Todo::new(i);
+            todo.value = value.to_string();
+            todo.depth = *depth;
+            todolist.insert_todo(i, todo);
+        }
+        app.todolists = vec![todolist];
+        app.todolist_idx = Some(0);
+        app
+    }
+
+    fn values(app: &App) -> Vec<(String, usize)> {
+        app.todolists[0].todos().iter().map(|todo| (todo.value.clone(), todo.depth)).collect()
+    }
+
+    // Stands in for another instance saving, with a modification time we haven't seen
+    fn save_elsewhere(theirs: &App) {
+        config::save(theirs).unwrap();
+        let later = config::modified().unwrap() + Duration::from_secs(5);
+        fs::File::options().write(true).open(config::data_path()).unwrap().set_modified(later).unwrap();
+    }
+
+    // An app that just saved its todos
+    fn saved_app(todos: &[(&str, usize)]) -> App {
+        let mut app = app_with(todos);
+        app.dirty = true;
+        assert!(app.save());
+        app
+    }
+
+    #[test]
+    fn touched_but_unchanged_file_is_ignored() {
+        let _home = config::temp_home();
+        let mut app = saved_app(&[("a", 0)]);
+        app.dirty = true;
+        let ours = app.todolists[0].todos()[0].id;
+        save_elsewhere(&app);
+        app.check_disk();
+        assert_eq!(app.mode, Mode::Normal);
+        assert_eq!(app.disk, config::stamp());
+        assert_eq!(app.todolists[0].todos()[0].id, ours);
+    }
+
+    #[test]
+    fn change_while_clean_is_reloaded() {
+        let _home = config::temp_home();
+        let mut app = saved_app(&[("a", 0)]);
+        save_elsewhere(&app_with(&[("b", 0), ("c", 1)]));
+        app.check_disk();
+        assert_eq!(app.mode, Mode::Normal);
+        assert_eq!(values(&app), [("b".to_string(), 0), ("c".to_string(), 1)]);
+        assert!(!app.dirty);
+        assert_eq!(app.disk, config::stamp());
+    }
+
+    #[test]
+    fn change_while_dirty_asks() {
+        let _home = config::temp_home();
+        let mut app = saved_app(&[("a", 0)]);
+        app.dirty = true;
+        save_elsewhere(&app_with(&[("b", 0)]));
+        app.check_disk();
+        assert_eq!(app.mode, Mode::Conflict);
+        // Saving asks too instead of writing over it
+        app.mode = Mode::Normal;
+        assert!(!app.save());
+        assert_eq!(app.mode, Mode::Conflict);
+    }
+
+    #[test]
+    fn file_appearing_after_startup_is_a_change() {
+        let _home = config::temp_home();
+        let mut app = app_with(&[("a", 0)]);
+        assert_eq!(app.disk, None);
+        save_elsewhere(&app_with(&[("b", 0)]));
+        app.dirty = true;
+        app.check_disk();
+        assert_eq!(app.mode, Mode::Conflict);
+
+        let mut app = app_with(&[("a", 0)]);
+        app.check_disk();
+        assert_eq!(values(&app), [("b".to_string(), 0)]);
+    }
+
+    #[test]
+    fn keeping_ours_saves_over_theirs() {
+        let _home = config::temp_home();
+        let mut app = saved_app(&[("a", 0)]);
+        app.dirty = true;
+        save_elsewhere(&app_with(&[("b", 0)]));
+        app.check_disk();
+        app.keep_ours();
+        assert_eq!(app.mode, Mode::Normal);
+        assert!(!app.dirty);
+        assert_eq!(values(&config::reload().unwrap()), [("a".to_string(), 0)]);
+    }
+
+    #[test]
+    fn merging_adds_their_new_todos_at_their_own_level() {
+        let _home = config::temp_home();
+        let mut app = saved_app(&[("a", 0)]);
+        app.dirty = true;
+        let mut theirs: App = serde_json::from_str(&serde_json::to_string(&app).unwrap()).unwrap();
+        // A new subtask of a todo we share, then a new todo with its own subtask
+        for (i, (value, depth)) in [("q", 1), ("p", 0), ("r", 1)].into_iter().enumerate() {
+            let mut todo = todo::Todo::new(i + 1);
+            todo.value = value.to_string();
+            todo.depth = depth;
+            theirs.todolists[0].insert_todo(i + 1, todo);
+        }
+        save_elsewhere(&theirs);
+        app.check_disk();
+        app.merge_theirs();
+        assert_eq!(app.mode, Mode::Normal);
+        let merged = [("a", 0), ("q", 0), ("p", 0), ("r", 1)].map(|(value, depth)| (value.to_string(), depth));
+        assert_eq!(values(&app), merged);
+        assert!(!app.dirty);
     }
 }
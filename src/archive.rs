@@ -0,0 +1,96 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::todo::Todo;
+use crate::todolist::TodoList;
+
+// A completed todo moved out of its list by `:clean`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ArchivedTodo {
+    pub todo: Todo,
+    pub completed_at: DateTime<Local>,
+}
+
+// Popup browsing the archived todos of every todolist
+#[derive(Default)]
+pub struct ArchiveView {
+    // Todolist index and archive index of every entry matching the query
+    pub entries: Vec<(usize, usize)>,
+    pub idx: usize,
+    pub query: String,
+    pub searching: bool,
+}
+
+impl ArchiveView {
+    // Rebuilds the entries after the archives or the query changed, newest first
+    pub fn refresh(&mut self, todolists: &[TodoList]) {
+        let query = self.query.to_lowercase();
+        let mut entries: Vec<(usize, usize)> = Vec::new();
+        for (list_idx, todolist) in todolists.iter().enumerate() {
+            for (archive_idx, archived) in todolist.archive.iter().enumerate() {
+                if archived.todo.value.to_lowercase().contains(&query) {
+                    entries.push((list_idx, archive_idx));
+                }
+            }
+        }
+        entries.sort_by_key(|&(list_idx, archive_idx)| {
+            std::cmp::Reverse(todolists[list_idx].archive[archive_idx].completed_at)
+        });
+        self.entries = entries;
+        self.idx = self.idx.min(self.entries.len().saturating_sub(1));
+    }
+
+    pub fn move_up(&mut self) {
+        self.idx = self.idx.saturating_sub(1);
+    }
+
+    pub fn move_down(&mut self) {
+        if self.idx + 1 < self.entries.len() {
+            self.idx += 1;
+        }
+    }
+
+    pub fn selected(&self) -> Option<(usize, usize)> {
+        self.entries.get(self.idx).copied()
+    }
+}
+
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Clear, List, ListItem, ListState};
+
+// The archive view needs the todolists to show the archived todos themselves
+pub struct ArchiveViewWidget<'a> {
+    pub view: &'a ArchiveView,
+    pub todolists: &'a [TodoList],
+}
+
+impl Widget for ArchiveViewWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let popup = area.centered(Constraint::Percentage(80), Constraint::Percentage(80));
+        let items = self.view.entries.iter().map(|&(list_idx, archive_idx)| {
+            let todolist = &self.todolists[list_idx];
+            let archived = &todolist.archive[archive_idx];
+            ListItem::new(Line::from(vec![
+                Span::raw(format!(" {} ", archived.completed_at.format("%Y-%m-%d %H:%M"))).dark_gray(),
+                Span::raw(format!("{} › ", todolist.title)).yellow(),
+                Span::raw(archived.todo.value.clone()),
+            ]))
+        });
+
+        let search = if self.view.searching || !self.view.query.is_empty() {
+            Line::from(format!(" /{} ", self.view.query))
+        } else {
+            Line::from(" Archive ").centered()
+        };
+        let block = Block::bordered()
+            .title(search)
+            .title_bottom(Line::from(" /: search, r: restore, esc: close ").centered())
+            .border_style(Color::Yellow);
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::default().black().on_yellow());
+        let mut state = ListState::default().with_selected(Some(self.view.idx));
+        Clear.render(popup, buf);
+        StatefulWidget::render(list, popup, buf, &mut state);
+    }
+}
@@ -3,6 +3,8 @@ use ratatui::widgets::BorderType;
 use ratatui::widgets::{Paragraph, Block};
 use serde::{Deserialize, Serialize};
 
+use crate::todo::Due;
+
 #[derive(Serialize, Deserialize)]
 pub struct CommandPrompt {
     pub value: String,
@@ -13,7 +15,24 @@ pub enum Command {
     Clean,
     Save,
     Quit,
+    ForceQuit,
     SaveAndQuit,
+    // Saves only if there are unsaved changes, then quits
+    Exit,
+    Set(String),
+    Tab(String),
+    Zen,
+    Due(String),
+    Every(String),
+    Sort(String),
+    Filter(String),
+    Tags,
+    Archive,
+    Purge,
+    Status(String),
+    Timesheet,
+    Pomodoro,
+    Estimate(String),
 }
 
 impl CommandPrompt {
@@ -26,16 +45,49 @@ impl CommandPrompt {
 
     pub fn parse(&mut self) -> Option<Command> {
         self.selected = false;
-        match std::mem::take(&mut self.value).as_str() {
+        let value = std::mem::take(&mut self.value);
+        let (name, args) = match value.split_once(' ') {
+            Some((name, args)) => (name, args.trim()),
+            None => (value.as_str(), ""),
+        };
+        match name {
             ":clean" => return Some(Command::Clean),
             ":w" => return Some(Command::Save),
             ":q" => return Some(Command::Quit),
+            ":q!" => return Some(Command::ForceQuit),
             ":wq" => return Some(Command::SaveAndQuit),
+            ":x" => return Some(Command::Exit),
+            ":set" => return Some(Command::Set(args.to_string())),
+            ":due" => return Some(Command::Due(args.to_string())),
+            ":every" => return Some(Command::Every(args.to_string())),
+            ":est" | ":estimate" => return Some(Command::Estimate(args.to_string())),
+            ":sort" => return Some(Command::Sort(args.to_string())),
+            ":filter" => return Some(Command::Filter(args.to_string())),
+            ":tags" => return Some(Command::Tags),
+            ":archive" => return Some(Command::Archive),
+            ":purge" => return Some(Command::Purge),
+            ":status" => return Some(Command::Status(args.to_string())),
+            ":timesheet" => return Some(Command::Timesheet),
+            ":pomodoro" => return Some(Command::Pomodoro),
+            ":zen" => return Some(Command::Zen),
+            ":tab" => return Some(Command::Tab(args.to_string())),
             _ => {},
         }
         return None;
     }
 
+    // What the command being typed will do, shown before it is confirmed
+    pub fn preview(&self) -> Option<String> {
+        let date = self.value.strip_prefix(":due ")?.trim();
+        if date.is_empty() {
+            return None;
+        }
+        match Due::parse(date) {
+            Ok(due) => Some(format!("→ {}", due)),
+            Err(e) => Some(e),
+        }
+    }
+
     pub fn select_command(&mut self) {
         self.selected = true;
         self.value = String::from(":");
@@ -51,8 +103,12 @@ impl Widget for &CommandPrompt {
             cursor = Span::from(" ").bg(Color::White)
         }
         let cmd_line = Line::from(vec![Span::from(&self.value), cursor]);
+        let inner = command_block.inner(area);
         let component = Paragraph::new(cmd_line).left_aligned().block(command_block);
 
         Widget::render(component, area, buf);
+        if let Some(preview) = self.preview() {
+            Line::from(preview).right_aligned().dark_gray().render(inner, buf);
+        }
     }
 }
@@ -1,12 +1,15 @@
 use core::panic;
 use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::path::PathBuf;
+use std::time::SystemTime;
 use home;
 
 use crate::app::App;
 
 const CONFIG_RELATIVE_PATH: &str = ".config/todolist-manager";
 const CONFIG_FILE_NAME: &str = "todos.json";
+const LOCK_FILE_NAME: &str = "todos.json.lock";
 
 fn config_dir() -> PathBuf {
     let home_dir = match home::home_dir() {
@@ -17,21 +20,43 @@ fn config_dir() -> PathBuf {
     return config_path;
 }
 
-pub fn save(app: &App) {
+// Where the todolists are saved
+pub fn data_path() -> PathBuf {
+    config_dir().join(CONFIG_FILE_NAME)
+}
+
+// The data path with the home directory shortened to `~`
+pub fn display_path() -> String {
+    let path = data_path();
+    match home::home_dir().and_then(|home| path.strip_prefix(home).ok().map(PathBuf::from)) {
+        Some(relative) => format!("~/{}", relative.display()),
+        None => path.display().to_string(),
+    }
+}
+
+// Returns how many todos were saved, fails if another instance holds the lock
+pub fn save(app: &App) -> std::io::Result<usize> {
     let config_dir = config_dir();
-    let config_path = config_dir.join(CONFIG_FILE_NAME);
-    if !config_dir.exists() {
-        match fs::create_dir(&config_dir){
-            Err(e) => panic!("failed to create dir with error: {}", e),
-            Ok(_) => ()
+    fs::create_dir_all(&config_dir)?;
+    if let Lock::HeldBy { pid, .. } = lock()? {
+        if pid != std::process::id() {
+            let message = format!("the data file is locked by pid {}", pid);
+            return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, message));
         }
-        print!("created dir: {}", config_path.display());
-    }
-    let serialize = serde_json::to_string(&app).unwrap();
-    match fs::write(config_path, serialize) {
-        Err(e) => panic!("write failed with error: {}", e),
-        Ok(_) => ()
     }
+    let serialize = serde_json::to_string(&app).map_err(std::io::Error::other)?;
+    fs::write(data_path(), serialize)?;
+    Ok(app.todolists.iter().map(|todolist| todolist.len()).sum())
+}
+
+// Saves next to the data file instead of over it, for changes that can't be saved
+// normally when the app has to exit. Returns where they went
+pub fn save_copy(app: &App) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(config_dir())?;
+    let path = config_dir().join(format!("{}.{}.conflict", CONFIG_FILE_NAME, std::process::id()));
+    let serialize = serde_json::to_string(&app).map_err(std::io::Error::other)?;
+    fs::write(&path, serialize)?;
+    Ok(path)
 }
 
 pub fn retrieve() -> std::result::Result<App, std::io::Error> {
@@ -47,9 +72,209 @@ pub fn retrieve() -> std::result::Result<App, std::io::Error> {
         Err(e) => return Err(e),
         Ok(result) => result,
     };
-    let app: App = match serde_json::from_str(&todos) {
+    let mut app: App = match serde_json::from_str(&todos) {
         Ok(data) => data,
         Err(_) => App::new(),
     };
+    app.sync_visibility();
+    app.assign_ids();
+    app.disk = stamp();
+    Ok(app)
+}
+
+// Reads the data file again, unlike `retrieve` a file that can't be parsed is an error
+pub fn reload() -> std::io::Result<App> {
+    let todos = fs::read_to_string(data_path())?;
+    let mut app: App = serde_json::from_str(&todos)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    app.sync_visibility();
+    app.assign_ids();
+    app.disk = stamp();
     Ok(app)
 }
+
+// The data file as we last read or wrote it, to notice when something else changes it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FileStamp {
+    pub modified: SystemTime,
+    pub hash: u64,
+}
+
+pub fn modified() -> Option<SystemTime> {
+    fs::metadata(data_path()).and_then(|metadata| metadata.modified()).ok()
+}
+
+// None if the data file doesn't exist or can't be read
+pub fn stamp() -> Option<FileStamp> {
+    let modified = modified()?;
+    let contents = fs::read(data_path()).ok()?;
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Some(FileStamp { modified, hash: hasher.finish() })
+}
+
+// Who holds the lock file that keeps two instances from saving over each other
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Lock {
+    Acquired,
+    // Held by another instance, stale if that process is gone
+    HeldBy { pid: u32, stale: bool },
+}
+
+fn lock_path() -> PathBuf {
+    config_dir().join(LOCK_FILE_NAME)
+}
+
+// Takes the lock by creating the lock file with our pid in it, unless someone else has it
+pub fn lock() -> std::io::Result<Lock> {
+    fs::create_dir_all(config_dir())?;
+    // Linked into place once the pid is written, so the lock file is never seen empty
+    let ours = write_pid_file()?;
+    let linked = fs::hard_link(&ours, lock_path());
+    let _ = fs::remove_file(&ours);
+    match linked {
+        Ok(()) => Ok(Lock::Acquired),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            // An unreadable lock file can't belong to a live instance
+            let pid = fs::read_to_string(lock_path())?.trim().parse().unwrap_or(0);
+            Ok(Lock::HeldBy { pid, stale: !is_running(pid) })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Takes the lock over from an instance that is gone
+pub fn steal_lock() -> std::io::Result<()> {
+    let ours = write_pid_file()?;
+    fs::rename(ours, lock_path())
+}
+
+// A file next to the lock file holding our pid, to be moved into place
+fn write_pid_file() -> std::io::Result<PathBuf> {
+    let path = config_dir().join(format!("{}.{}", LOCK_FILE_NAME, std::process::id()));
+    fs::write(&path, std::process::id().to_string())?;
+    Ok(path)
+}
+
+// Gives the lock back, if it is still ours
+pub fn unlock() {
+    let ours = fs::read_to_string(lock_path())
+        .is_ok_and(|pid| pid.trim() == std::process::id().to_string());
+    if ours {
+        let _ = fs::remove_file(lock_path());
+    }
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+    // Signal 0 only checks that the process exists, EPERM means it does but isn't ours
+    let alive = unsafe { libc::kill(pid, 0) } == 0;
+    alive || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// Without a way to check, a lock is taken to belong to a live instance rather than
+// risk saving over it
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+// Points the config dir at a fresh home directory for as long as the guard is held,
+// tests that touch the data file take turns since HOME is shared by the whole process
+#[cfg(test)]
+pub fn temp_home() -> std::sync::MutexGuard<'static, ()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    static HOME: Mutex<()> = Mutex::new(());
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let guard = HOME.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let count = COUNT.fetch_add(1, Ordering::Relaxed);
+    let home = std::env::temp_dir().join(format!("todolist-manager-test-{}-{}", std::process::id(), count));
+    let _ = fs::remove_dir_all(&home);
+    fs::create_dir_all(&home).expect("temp dir is writable");
+    std::env::set_var("HOME", &home);
+    guard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Far above any real pid limit, so never running
+    const GONE: u32 = i32::MAX as u32;
+
+    fn hold_lock(pid: u32) {
+        fs::create_dir_all(config_dir()).unwrap();
+        fs::write(lock_path(), pid.to_string()).unwrap();
+    }
+
+    fn lock_holder() -> Option<String> {
+        fs::read_to_string(lock_path()).ok()
+    }
+
+    #[test]
+    fn acquires_a_free_lock() {
+        let _home = temp_home();
+        assert_eq!(lock().unwrap(), Lock::Acquired);
+        assert_eq!(lock_holder(), Some(std::process::id().to_string()));
+        // Nothing is left over from writing the pid
+        let files = fs::read_dir(config_dir()).unwrap().count();
+        assert_eq!(files, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn live_holder_keeps_the_lock() {
+        let _home = temp_home();
+        let parent = std::os::unix::process::parent_id();
+        hold_lock(parent);
+        assert_eq!(lock().unwrap(), Lock::HeldBy { pid: parent, stale: false });
+        assert_eq!(lock_holder(), Some(parent.to_string()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn gone_holder_leaves_a_stale_lock() {
+        let _home = temp_home();
+        hold_lock(GONE);
+        assert_eq!(lock().unwrap(), Lock::HeldBy { pid: GONE, stale: true });
+        steal_lock().unwrap();
+        assert_eq!(lock_holder(), Some(std::process::id().to_string()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_lock_is_stale() {
+        let _home = temp_home();
+        fs::create_dir_all(config_dir()).unwrap();
+        fs::write(lock_path(), "").unwrap();
+        assert_eq!(lock().unwrap(), Lock::HeldBy { pid: 0, stale: true });
+    }
+
+    #[test]
+    fn unlock_releases_our_lock_only() {
+        let _home = temp_home();
+        hold_lock(GONE);
+        unlock();
+        assert_eq!(lock_holder(), Some(GONE.to_string()));
+        fs::remove_file(lock_path()).unwrap();
+        assert_eq!(lock().unwrap(), Lock::Acquired);
+        unlock();
+        assert_eq!(lock_holder(), None);
+    }
+
+    #[test]
+    fn save_refuses_while_locked_elsewhere() {
+        let _home = temp_home();
+        hold_lock(GONE);
+        let error = save(&App::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(!data_path().exists());
+    }
+}
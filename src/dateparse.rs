@@ -0,0 +1,241 @@
+use chrono::{Datelike, Days, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+use crate::todo::Due;
+
+// Turns human friendly input into a due date relative to `now`.
+//
+// Understands:
+//   today, tomorrow, yesterday
+//   mon, friday, next fri
+//   in 3 days, in 2 weeks, in 1 month, in 4 hours, in 30 minutes, +3d, +1mo, +30m
+//   next week, next month, eow, eom, eoy
+//   2026-11-02, 11/02
+// optionally followed by a time such as 14:00, 9am or 5:30pm
+pub fn parse(input: &str, now: NaiveDateTime) -> Result<Due, String> {
+    let input = input.trim().to_lowercase();
+    let mut words: Vec<&str> = input.split_whitespace().collect();
+    if words.is_empty() {
+        return Err(String::from("empty date"));
+    }
+
+    // A trailing time applies to whatever day the rest describes
+    let mut time = None;
+    if let Some(parsed) = words.last().and_then(|word| parse_time(word)) {
+        time = Some(parsed);
+        words.pop();
+    }
+    if words.is_empty() {
+        // A time on its own means today, or tomorrow if it has already passed
+        let time = time.expect("a time was parsed");
+        let date = if now.time() < time { now.date() } else { now.date() + Days::new(1) };
+        return Ok(Due { date, time: Some(time) });
+    }
+
+    let today = now.date();
+    let date = match words.as_slice() {
+        ["today" | "tod"] => today,
+        ["tomorrow" | "tmr" | "tom"] => today + Days::new(1),
+        ["yesterday"] => today - Days::new(1),
+        ["eow"] => next_weekday(today, Weekday::Sun, true),
+        ["eom"] => end_of_month(today),
+        ["eoy"] => NaiveDate::from_ymd_opt(today.year(), 12, 31).expect("december 31st exists"),
+        ["next", "week"] => today + Days::new(7),
+        ["next", "month"] => add_months(today, 1)?,
+        ["next", "year"] => add_months(today, 12)?,
+        ["next", day] => next_weekday(today, parse_weekday(day)?, false),
+        ["in", amount, unit] => {
+            let amount = amount.parse::<u32>().map_err(|_| format!("invalid amount: {}", amount))?;
+            return offset(now, amount, unit, time);
+        }
+        [word] if word.starts_with('+') => {
+            let split = word.find(|c: char| c.is_alphabetic()).unwrap_or(word.len());
+            let amount = word[1..split].parse::<u32>().map_err(|_| format!("invalid offset: {}", word))?;
+            return offset(now, amount, &word[split..], time);
+        }
+        [word] => match parse_weekday(word) {
+            Ok(weekday) => next_weekday(today, weekday, true),
+            Err(_) => parse_date(word, today)?,
+        },
+        _ => return Err(format!("unrecognized date: {}", input)),
+    };
+    Ok(Due { date, time })
+}
+
+// Moves `now` forward by an amount of some unit, units below a day keep the time of day.
+// A bare `m` is minutes like in estimates, months are `mo`
+fn offset(now: NaiveDateTime, amount: u32, unit: &str, time: Option<NaiveTime>) -> Result<Due, String> {
+    let today = now.date();
+    let date = match unit {
+        "d" | "day" | "days" => add_days(today, amount as u64)?,
+        "w" | "week" | "weeks" => add_days(today, 7 * amount as u64)?,
+        "mo" | "month" | "months" => add_months(today, amount)?,
+        "y" | "year" | "years" => add_months(today, amount.checked_mul(12).ok_or(OUT_OF_RANGE)?)?,
+        "h" | "hour" | "hours" => return add_duration(now, Duration::hours(amount as i64)),
+        "m" | "min" | "mins" | "minute" | "minutes" => return add_duration(now, Duration::minutes(amount as i64)),
+        _ => return Err(format!("unknown unit: {}", unit)),
+    };
+    Ok(Due { date, time })
+}
+
+const OUT_OF_RANGE: &str = "date out of range";
+
+fn add_days(date: NaiveDate, days: u64) -> Result<NaiveDate, String> {
+    date.checked_add_days(Days::new(days)).ok_or(String::from(OUT_OF_RANGE))
+}
+
+fn add_months(date: NaiveDate, months: u32) -> Result<NaiveDate, String> {
+    date.checked_add_months(Months::new(months)).ok_or(String::from(OUT_OF_RANGE))
+}
+
+// Keeps the time of day, for offsets below a day
+fn add_duration(now: NaiveDateTime, duration: Duration) -> Result<Due, String> {
+    let at = now.checked_add_signed(duration).ok_or(String::from(OUT_OF_RANGE))?;
+    Ok(Due { date: at.date(), time: Some(at.time()) })
+}
+
+fn end_of_month(date: NaiveDate) -> NaiveDate {
+    let first = date.with_day(1).expect("every month has a first day");
+    first + Months::new(1) - Days::new(1)
+}
+
+// The next given weekday after `date`, or `date` itself if it already is that day and `inclusive`
+fn next_weekday(date: NaiveDate, weekday: Weekday, inclusive: bool) -> NaiveDate {
+    let mut days = (7 + weekday.num_days_from_monday() - date.weekday().num_days_from_monday()) % 7;
+    if days == 0 && !inclusive {
+        days = 7;
+    }
+    date + Days::new(days as u64)
+}
+
+pub fn parse_weekday(word: &str) -> Result<Weekday, String> {
+    match word {
+        "mon" | "monday" => Ok(Weekday::Mon),
+        "tue" | "tues" | "tuesday" => Ok(Weekday::Tue),
+        "wed" | "wednesday" => Ok(Weekday::Wed),
+        "thu" | "thur" | "thurs" | "thursday" => Ok(Weekday::Thu),
+        "fri" | "friday" => Ok(Weekday::Fri),
+        "sat" | "saturday" => Ok(Weekday::Sat),
+        "sun" | "sunday" => Ok(Weekday::Sun),
+        _ => Err(format!("unknown weekday: {}", word)),
+    }
+}
+
+// Full ISO dates, or month/day in the current year
+fn parse_date(word: &str, today: NaiveDate) -> Result<NaiveDate, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(word, "%Y-%m-%d") {
+        return Ok(date);
+    }
+    if let Some((month, day)) = word.split_once('/') {
+        if let (Ok(month), Ok(day)) = (month.parse(), day.parse()) {
+            if let Some(date) = NaiveDate::from_ymd_opt(today.year(), month, day) {
+                return Ok(date);
+            }
+        }
+    }
+    Err(format!("unrecognized date: {}", word))
+}
+
+// 24 hour `14:00`, or 12 hour `9am` / `5:30pm`
+fn parse_time(word: &str) -> Option<NaiveTime> {
+    if let Ok(time) = NaiveTime::parse_from_str(word, "%H:%M") {
+        return Some(time);
+    }
+    let (clock, pm) = match (word.strip_suffix("am"), word.strip_suffix("pm")) {
+        (Some(clock), _) => (clock, false),
+        (_, Some(clock)) => (clock, true),
+        _ => return None,
+    };
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) => (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?),
+        None => (clock.parse::<u32>().ok()?, 0),
+    };
+    if !(1..=12).contains(&hour) {
+        return None;
+    }
+    let hour = hour % 12 + if pm { 12 } else { 0 };
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A Wednesday morning
+    fn now() -> NaiveDateTime {
+        date(2026, 10, 14).and_hms_opt(10, 0, 0).unwrap()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn at(hour: u32, minute: u32) -> Option<NaiveTime> {
+        NaiveTime::from_hms_opt(hour, minute, 0)
+    }
+
+    fn parsed(input: &str) -> Due {
+        parse(input, now()).unwrap()
+    }
+
+    #[test]
+    fn relative_days() {
+        assert_eq!(parsed("today"), Due { date: date(2026, 10, 14), time: None });
+        assert_eq!(parsed("tomorrow"), Due { date: date(2026, 10, 15), time: None });
+        assert_eq!(parsed("in 3 days"), Due { date: date(2026, 10, 17), time: None });
+        assert_eq!(parsed("+2w"), Due { date: date(2026, 10, 28), time: None });
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(parsed("fri").date, date(2026, 10, 16));
+        assert_eq!(parsed("next fri").date, date(2026, 10, 16));
+        assert_eq!(parsed("wed").date, date(2026, 10, 14));
+        assert_eq!(parsed("next wed").date, date(2026, 10, 21));
+    }
+
+    #[test]
+    fn period_ends() {
+        assert_eq!(parsed("eow").date, date(2026, 10, 18));
+        assert_eq!(parsed("eom").date, date(2026, 10, 31));
+        assert_eq!(parsed("eoy").date, date(2026, 12, 31));
+    }
+
+    #[test]
+    fn calendar_dates() {
+        assert_eq!(parsed("11/02").date, date(2026, 11, 2));
+        assert_eq!(parsed("2026-11-02").date, date(2026, 11, 2));
+        assert!(parse("13/40", now()).is_err());
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(parsed("tomorrow 14:00"), Due { date: date(2026, 10, 15), time: at(14, 0) });
+        assert_eq!(parsed("fri 9am"), Due { date: date(2026, 10, 16), time: at(9, 0) });
+        assert_eq!(parsed("5:30pm"), Due { date: date(2026, 10, 14), time: at(17, 30) });
+        // Already past today
+        assert_eq!(parsed("9am"), Due { date: date(2026, 10, 15), time: at(9, 0) });
+        assert_eq!(parsed("12am").time, at(0, 0));
+        assert_eq!(parsed("12pm").time, at(12, 0));
+    }
+
+    #[test]
+    fn minutes_and_months() {
+        assert_eq!(parsed("+30m"), Due { date: date(2026, 10, 14), time: at(10, 30) });
+        assert_eq!(parsed("+1mo").date, date(2026, 11, 14));
+        assert_eq!(parsed("in 1 month").date, date(2026, 11, 14));
+        assert_eq!(parsed("in 4 hours"), Due { date: date(2026, 10, 14), time: at(14, 0) });
+        assert!(parse("+3x", now()).is_err());
+    }
+
+    #[test]
+    fn out_of_range() {
+        let out_of_range = Err(String::from("date out of range"));
+        assert_eq!(parse("+99999999d", now()), out_of_range);
+        assert_eq!(parse("in 4000000000 weeks", now()), out_of_range);
+        assert_eq!(parse("+4000000000mo", now()), out_of_range);
+        assert_eq!(parse("+4000000000y", now()), out_of_range);
+        assert_eq!(parse("in 4000000000 hours", now()), out_of_range);
+        assert!(parse("in 999999999 hours", now()).is_ok());
+        assert!(parse("+4000000000m", now()).is_ok());
+    }
+}
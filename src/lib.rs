@@ -1,6 +1,13 @@
 pub mod app;
+pub mod archive;
 pub mod command;
 pub mod config;
+pub mod dateparse;
+pub mod pomodoro;
+pub mod settings;
+pub mod status;
+pub mod tags;
+pub mod timesheet;
 pub mod todo;
 pub mod todolist;
 pub mod ui;
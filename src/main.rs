@@ -1,5 +1,5 @@
 use crossterm::{
-    event::{self, KeyCode, KeyEventKind, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags}, execute, terminal::{
+    event::{self, DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEventKind, KeyboardEnhancementFlags, MouseButton, MouseEventKind, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags}, execute, terminal::{
         disable_raw_mode, enable_raw_mode, EnterAlternateScreen,
         LeaveAlternateScreen,
     }, ExecutableCommand
@@ -11,7 +11,11 @@ use todolist_manager::{
 };
 
 use ratatui::prelude::{CrosstermBackend, Terminal};
+#[cfg(unix)]
+use signal_hook::consts::{SIGHUP, SIGTERM};
 use std::io::{stdout, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 fn main() -> Result<()> {
     let mut out = stdout();
@@ -22,6 +26,7 @@ fn main() -> Result<()> {
         )
     );
     let _ = stdout().execute(EnterAlternateScreen);
+    let _ = stdout().execute(EnableMouseCapture);
     enable_raw_mode()?;
     let backend = CrosstermBackend::new(out);
     let mut terminal = Terminal::new(backend)?;
@@ -31,25 +36,126 @@ fn main() -> Result<()> {
         Err(_) => App::new(),
         Ok(app) => app,
     };
+    app.apply_lock(config::lock());
+    // Set when the terminal goes away or we're asked to stop, so we can save before exiting
+    let terminate = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    for signal in [SIGTERM, SIGHUP] {
+        let _ = signal_hook::flag::register(signal, Arc::clone(&terminate));
+    }
+    // First key of a two key sequence such as `gt`
+    let mut pending_key: Option<char> = None;
     loop{
+        if terminate.load(Ordering::Relaxed) {
+            app.save_before_exit();
+            break;
+        }
+        app.tick();
         let _ = terminal.draw(|f| {ui::ui(f, &app);});
         
-        if event::poll(std::time::Duration::from_millis(200))? {
-            if let event::Event::Key(key) = event::read()? {
+        // Losing the terminal shows up as an input error, save before giving up
+        let ready = event::poll(std::time::Duration::from_millis(200))
+            .and_then(|ready| if ready { event::read().map(Some) } else { Ok(None) });
+        let event = match ready {
+            Ok(event) => event,
+            Err(e) => {
+                app.save_before_exit();
+                config::unlock();
+                return Err(e);
+            }
+        };
+        if let Some(event) = event {
+            app.record_input();
+            if let event::Event::Mouse(mouse) = event {
+                match mouse.kind {
+                    MouseEventKind::Down(MouseButton::Left) => {
+                        app.click(mouse.column, mouse.row);
+                    },
+                    MouseEventKind::Up(MouseButton::Left) => {
+                        app.release(mouse.column, mouse.row);
+                    },
+                    MouseEventKind::ScrollDown => {
+                        app.scroll(mouse.column, mouse.row, true);
+                    },
+                    MouseEventKind::ScrollUp => {
+                        app.scroll(mouse.column, mouse.row, false);
+                    },
+                    _ => {},
+                }
+            }
+            if let event::Event::Key(key) = event {
                 match app.mode {
                     Mode::Normal => {
-                        if key.kind == KeyEventKind::Press{
+                        if key.kind == KeyEventKind::Press && key.modifiers == event::KeyModifiers::CONTROL {
                             match key.code {
-                                KeyCode::Char('q') => {
-                                    config::save(&app);
+                                KeyCode::Char('d') => {
+                                    app.scroll_half_page_down();
+                                },
+                                KeyCode::Char('u') => {
+                                    app.scroll_half_page_up();
+                                },
+                                _ => {},
+                            }
+                        }
+                        else if key.kind == KeyEventKind::Press && pending_key.is_some() {
+                            match (pending_key.take(), key.code) {
+                                (Some('g'), KeyCode::Char('t')) => {
+                                    app.next_todolist();
+                                },
+                                (Some('g'), KeyCode::Char('T')) => {
+                                    app.prev_todolist();
+                                },
+                                (Some('>'), KeyCode::Char('>')) => {
+                                    app.indent();
+                                },
+                                (Some('<'), KeyCode::Char('<')) => {
+                                    app.outdent();
+                                },
+                                (Some('z'), KeyCode::Char('a')) => {
+                                    app.toggle_collapsed();
+                                },
+                                _ => {},
+                            }
+                        }
+                        else if key.kind == KeyEventKind::Press{
+                            match key.code {
+                                // Only quits once everything is saved
+                                KeyCode::Char('q') if app.save() => {
                                     break;
                                 }, 
                                 KeyCode::Char('x') => {
                                     app.toggle_completed();
                                 },
+                                KeyCode::Char('c') => {
+                                    app.cycle_status();
+                                },
                                 KeyCode::Char('d') => {
                                     app.delete_todo();
                                 },
+                                KeyCode::Char(prefix @ ('g' | '>' | '<' | 'z')) => {
+                                    pending_key = Some(prefix);
+                                },
+                                KeyCode::Char('#') => {
+                                    app.open_tag_browser();
+                                },
+                                KeyCode::Char('C') => {
+                                    app.toggle_hide_completed();
+                                },
+                                KeyCode::Char('y') => {
+                                    app.yank_blocker();
+                                },
+                                KeyCode::Char('t') => {
+                                    app.toggle_timer();
+                                },
+                                KeyCode::Char('P') => {
+                                    app.toggle_pomodoro();
+                                },
+                                KeyCode::Char('b') => {
+                                    app.add_blocker();
+                                },
+                                KeyCode::Char('B') => {
+                                    app.clear_blockers();
+                                },
                                 KeyCode::Char('o') => {
                                     app.create_todo();
                                 },
@@ -72,8 +178,17 @@ fn main() -> Result<()> {
                                 KeyCode::Char('V') => {
                                     app.toggle_visual();
                                 }
+                                KeyCode::Char('Z') => {
+                                    app.toggle_zen();
+                                }
+                                KeyCode::Char('+') => {
+                                    app.raise_priority();
+                                }
+                                KeyCode::Char('-') => {
+                                    app.lower_priority();
+                                }
                                 KeyCode::Char('s') => {
-                                    config::save(&app);
+                                    app.save();
                                 }
                                 KeyCode::Char('j') => {
                                     app.move_down();
@@ -104,15 +219,29 @@ fn main() -> Result<()> {
                         }
                     },
                     Mode::Visual => {
-                        if key.kind == KeyEventKind::Press{
+                        if key.kind == KeyEventKind::Press && key.modifiers == event::KeyModifiers::CONTROL {
                             match key.code {
-                                KeyCode::Char('q') => {
-                                    config::save(&app);
+                                KeyCode::Char('d') => {
+                                    app.scroll_half_page_down();
+                                },
+                                KeyCode::Char('u') => {
+                                    app.scroll_half_page_up();
+                                },
+                                _ => {},
+                            }
+                        }
+                        else if key.kind == KeyEventKind::Press{
+                            match key.code {
+                                // Only quits once everything is saved
+                                KeyCode::Char('q') if app.save() => {
                                     break;
                                 }, 
                                 KeyCode::Char('x') => {
                                     app.toggle_completed();
                                 },
+                                KeyCode::Char('c') => {
+                                    app.cycle_status();
+                                },
                                 KeyCode::Char('d') => {
                                     app.delete_todo();
                                 },
@@ -125,8 +254,17 @@ fn main() -> Result<()> {
                                 KeyCode::Char('V') => {
                                     app.toggle_visual();
                                 }
+                                KeyCode::Char('Z') => {
+                                    app.toggle_zen();
+                                }
+                                KeyCode::Char('+') => {
+                                    app.raise_priority();
+                                }
+                                KeyCode::Char('-') => {
+                                    app.lower_priority();
+                                }
                                 KeyCode::Char('s') => {
-                                    config::save(&app);
+                                    app.save();
                                 }
                                 KeyCode::Char('j') => {
                                     app.move_down();
@@ -168,6 +306,106 @@ fn main() -> Result<()> {
                             }
                         }
                     },
+                    Mode::Tags => {
+                        if key.kind == KeyEventKind::Press {
+                            match key.code {
+                                KeyCode::Enter => {
+                                    app.apply_tag_browser();
+                                },
+                                KeyCode::Esc | KeyCode::Char('q') => {
+                                    app.close_tag_browser();
+                                },
+                                KeyCode::Char('j') | KeyCode::Down => {
+                                    app.tag_browser.move_down();
+                                },
+                                KeyCode::Char('k') | KeyCode::Up => {
+                                    app.tag_browser.move_up();
+                                },
+                                _ => {}
+                            }
+                        }
+                    },
+                    Mode::Archive => {
+                        if key.kind == KeyEventKind::Press && app.archive_view.searching {
+                            match key.code {
+                                KeyCode::Enter | KeyCode::Esc => {
+                                    app.stop_archive_search();
+                                },
+                                KeyCode::Backspace => {
+                                    app.archive_search_backspace();
+                                },
+                                KeyCode::Char(val) => {
+                                    app.archive_search_char(val);
+                                },
+                                _ => {}
+                            }
+                        }
+                        else if key.kind == KeyEventKind::Press {
+                            match key.code {
+                                KeyCode::Esc | KeyCode::Char('q') => {
+                                    app.close_archive();
+                                },
+                                KeyCode::Char('/') => {
+                                    app.start_archive_search();
+                                },
+                                KeyCode::Char('r') => {
+                                    app.restore_archived();
+                                },
+                                KeyCode::Char('j') | KeyCode::Down => {
+                                    app.archive_view.move_down();
+                                },
+                                KeyCode::Char('k') | KeyCode::Up => {
+                                    app.archive_view.move_up();
+                                },
+                                _ => {}
+                            }
+                        }
+                    },
+                    Mode::StaleLock => {
+                        if key.kind == KeyEventKind::Press {
+                            match key.code {
+                                KeyCode::Char('s') => {
+                                    app.steal_lock();
+                                },
+                                KeyCode::Char('r') | KeyCode::Esc => {
+                                    app.open_read_only();
+                                },
+                                _ => {}
+                            }
+                        }
+                    },
+                    Mode::Conflict => {
+                        if key.kind == KeyEventKind::Press {
+                            match key.code {
+                                KeyCode::Char('r') => {
+                                    app.reload();
+                                },
+                                KeyCode::Char('k') => {
+                                    app.keep_ours();
+                                },
+                                KeyCode::Char('m') => {
+                                    app.merge_theirs();
+                                },
+                                _ => {}
+                            }
+                        }
+                    },
+                    Mode::Timesheet => {
+                        if key.kind == KeyEventKind::Press {
+                            match key.code {
+                                KeyCode::Esc | KeyCode::Char('q') => {
+                                    app.close_timesheet();
+                                },
+                                KeyCode::Char('j') | KeyCode::Down => {
+                                    app.timesheet.scroll_down();
+                                },
+                                KeyCode::Char('k') | KeyCode::Up => {
+                                    app.timesheet.scroll_up();
+                                },
+                                _ => {}
+                            }
+                        }
+                    },
                     Mode::Command => {
                         if key.kind == KeyEventKind::Press {
                             match key.code {
@@ -192,6 +430,8 @@ fn main() -> Result<()> {
         }
     }
 
+    config::unlock();
+    let _ = stdout().execute(DisableMouseCapture);
     let _ = stdout().execute(LeaveAlternateScreen);
     let _ = stdout().execute(PopKeyboardEnhancementFlags);
     disable_raw_mode()?;
@@ -0,0 +1,99 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use chrono::{DateTime, Duration, Local};
+
+use crate::settings::Settings;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Phase {
+    Work,
+    ShortBreak,
+    LongBreak,
+}
+
+// Work and break intervals run against a single todo
+pub struct Pomodoro {
+    pub todo_id: u64,
+    pub phase: Phase,
+    pub started: DateTime<Local>,
+    // Work intervals finished since the pomodoro was started
+    pub cycles: u32,
+}
+
+impl Pomodoro {
+    pub fn new(todo_id: u64) -> Pomodoro {
+        Pomodoro {
+            todo_id,
+            phase: Phase::Work,
+            started: Local::now(),
+            cycles: 0,
+        }
+    }
+
+    pub fn length(&self, settings: &Settings) -> Duration {
+        let minutes = match self.phase {
+            Phase::Work => settings.pomodoro,
+            Phase::ShortBreak => settings.shortbreak,
+            Phase::LongBreak => settings.longbreak,
+        };
+        Duration::minutes(minutes as i64)
+    }
+
+    pub fn remaining(&self, settings: &Settings, now: DateTime<Local>) -> Duration {
+        self.started + self.length(settings) - now
+    }
+
+    // Moves on to the next interval once the current one is over, returning the
+    // phase that just ended
+    pub fn advance(&mut self, settings: &Settings, now: DateTime<Local>) -> Option<Phase> {
+        if self.remaining(settings, now) > Duration::zero() {
+            return None;
+        }
+        let ended = self.phase;
+        self.phase = match ended {
+            Phase::Work => {
+                self.cycles += 1;
+                if settings.longbreakafter > 0 && self.cycles.is_multiple_of(settings.longbreakafter) {
+                    Phase::LongBreak
+                } else {
+                    Phase::ShortBreak
+                }
+            }
+            Phase::ShortBreak | Phase::LongBreak => Phase::Work,
+        };
+        self.started = now;
+        Some(ended)
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Phase::Work => write!(f, "work"),
+            Phase::ShortBreak => write!(f, "break"),
+            Phase::LongBreak => write!(f, "long break"),
+        }
+    }
+}
+
+// Rings the terminal bell and runs the configured command, if any, in the background
+// with the phase just started in `POMODORO_PHASE`
+pub fn notify(command: &str, phase: Phase) {
+    let mut out = std::io::stdout();
+    let _ = out.write_all(b"\x07");
+    let _ = out.flush();
+    if !command.is_empty() {
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("POMODORO_PHASE", phase.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        if let Ok(mut child) = child {
+            std::thread::spawn(move || child.wait());
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+
+use crate::status::{self, Status};
+
+// User tweakable options, persisted alongside the todolists
+// and changed at runtime with `:set <option>=<value>`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Settings {
+    // Minimum number of todos kept visible above and below the selection
+    pub scrolloff: usize,
+    // Narrowest a todolist is drawn before the rest are scrolled out of view
+    pub listwidth: u16,
+    // Hides completed todos in every todolist
+    pub hidecompleted: bool,
+    // Shows when the selected todo was created, updated and completed in the detail pane
+    pub timestamps: bool,
+    // Shows todolist progress as a percentage instead of completed/total
+    pub percent: bool,
+    // Workflow a todo moves through, in the order `c` cycles through them
+    pub statuses: Vec<Status>,
+    // Seconds between saves while there are unsaved changes, 0 turns it off
+    pub autosave: u64,
+    // Seconds without input after which unsaved changes are saved, 0 turns it off
+    pub autosaveidle: u64,
+    // Length in minutes of pomodoro work intervals and breaks
+    pub pomodoro: u32,
+    pub shortbreak: u32,
+    pub longbreak: u32,
+    // Number of work intervals before a long break, 0 for never
+    pub longbreakafter: u32,
+    // Shell command run whenever a pomodoro interval changes. Not saved, anything able to
+    // write the data file could otherwise have it run
+    #[serde(skip)]
+    pub pomodorocmd: String,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            scrolloff: 2,
+            listwidth: 30,
+            hidecompleted: false,
+            timestamps: true,
+            percent: false,
+            statuses: status::default_statuses(),
+            autosave: 60,
+            autosaveidle: 10,
+            pomodoro: 25,
+            shortbreak: 5,
+            longbreak: 15,
+            longbreakafter: 4,
+            pomodorocmd: String::new(),
+        }
+    }
+}
+
+impl Settings {
+    // Applies an assignment such as `scrolloff=3`, or `hidecompleted` / `nohidecompleted`
+    // for switches, returning an error message for the user
+    pub fn set(&mut self, assignment: &str) -> Result<(), String> {
+        let (name, value) = match assignment.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => (assignment.trim(), ""),
+        };
+        let (name, switch) = match name.strip_prefix("no") {
+            Some(name) if value.is_empty() => (name, false),
+            _ => (name, true),
+        };
+        match name {
+            "hidecompleted" | "hc" => {
+                self.hidecompleted = switch;
+            }
+            "timestamps" | "ts" => {
+                self.timestamps = switch;
+            }
+            "percent" => {
+                self.percent = switch;
+            }
+            "statuses" => {
+                self.statuses = status::parse_statuses(value)?;
+            }
+            "autosave" => {
+                self.autosave = value.parse().map_err(|_| format!("invalid autosave: {}", value))?;
+            }
+            "autosaveidle" => {
+                self.autosaveidle = value.parse().map_err(|_| format!("invalid autosaveidle: {}", value))?;
+            }
+            "pomodoro" => {
+                self.pomodoro = parse_minutes(name, value)?;
+            }
+            "shortbreak" => {
+                self.shortbreak = parse_minutes(name, value)?;
+            }
+            "longbreak" => {
+                self.longbreak = parse_minutes(name, value)?;
+            }
+            "longbreakafter" => {
+                self.longbreakafter = value.parse().map_err(|_| format!("invalid longbreakafter: {}", value))?;
+            }
+            "pomodorocmd" => {
+                self.pomodorocmd = value.to_string();
+            }
+            "scrolloff" | "so" => {
+                self.scrolloff = value.parse().map_err(|_| format!("invalid scrolloff: {}", value))?;
+            }
+            "listwidth" | "lw" => {
+                self.listwidth = value.parse().map_err(|_| format!("invalid listwidth: {}", value))?;
+            }
+            _ => return Err(format!("unknown option: {}", name)),
+        }
+        Ok(())
+    }
+}
+
+fn parse_minutes(name: &str, value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(minutes) if minutes > 0 => Ok(minutes),
+        _ => Err(format!("invalid {}: {}", name, value)),
+    }
+}
@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+use crate::todo::Todo;
+
+// A step in the workflow of a todo, shown as its glyph inside the checkbox
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Status {
+    pub name: String,
+    pub glyph: char,
+    // Finished todos count as completed, `:clean` archives them
+    pub finished: bool,
+}
+
+impl Status {
+    fn new(name: &str, glyph: char, finished: bool) -> Status {
+        Status { name: String::from(name), glyph, finished }
+    }
+}
+
+pub fn default_statuses() -> Vec<Status> {
+    vec![
+        Status::new("todo", ' ', false),
+        Status::new("in-progress", '~', false),
+        Status::new("blocked", '!', false),
+        Status::new("done", 'x', true),
+        Status::new("cancelled", '-', true),
+    ]
+}
+
+// Parses `todo: ,doing:~,done:x*`, a name with an optional glyph after `:`,
+// a trailing `*` marks the status as finished
+pub fn parse_statuses(input: &str) -> Result<Vec<Status>, String> {
+    let mut statuses = Vec::new();
+    for entry in input.split(',') {
+        let (entry, finished) = match entry.strip_suffix('*') {
+            Some(entry) => (entry, true),
+            None => (entry, false),
+        };
+        let (name, glyph) = match entry.split_once(':') {
+            Some((name, glyph)) => {
+                let mut chars = glyph.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(glyph), None) => (name, glyph),
+                    _ => return Err(format!("glyph must be a single character: {}", glyph)),
+                }
+            }
+            None => (entry, entry.chars().next().unwrap_or(' ')),
+        };
+        if name.is_empty() {
+            return Err(String::from("status name can't be empty"));
+        }
+        statuses.push(Status::new(name, glyph, finished));
+    }
+    if !statuses.iter().any(|status| status.finished) || statuses.iter().all(|status| status.finished) {
+        return Err(String::from("statuses need at least one open and one finished status"));
+    }
+    Ok(statuses)
+}
+
+// The status of a todo, falling back on the first open or finished status
+// for todos whose status is unknown
+pub fn status_of<'a>(statuses: &'a [Status], todo: &Todo) -> &'a Status {
+    statuses.iter()
+        .find(|status| status.name == todo.status && status.finished == todo.completed)
+        .unwrap_or_else(|| first(statuses, todo.completed))
+}
+
+pub fn first(statuses: &[Status], finished: bool) -> &Status {
+    statuses.iter()
+        .find(|status| status.finished == finished)
+        .expect("statuses always have an open and a finished status")
+}
+
+pub fn next<'a>(statuses: &'a [Status], todo: &Todo) -> &'a Status {
+    let current = status_of(statuses, todo);
+    let idx = statuses.iter().position(|status| status == current).unwrap_or(0);
+    &statuses[(idx + 1) % statuses.len()]
+}
+
+pub fn find<'a>(statuses: &'a [Status], name: &str) -> Option<&'a Status> {
+    statuses.iter().find(|status| status.name.eq_ignore_ascii_case(name))
+}
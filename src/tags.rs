@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+
+use crate::todolist::TodoList;
+
+// Popup listing every tag in use with how many todos carry it
+#[derive(Default)]
+pub struct TagBrowser {
+    pub tags: Vec<(String, usize)>,
+    pub idx: usize,
+}
+
+impl TagBrowser {
+    pub fn new(todolists: &[TodoList]) -> TagBrowser {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for todolist in todolists {
+            for todo in todolist.todos() {
+                for tag in todo.tags() {
+                    *counts.entry(tag.to_lowercase()).or_default() += 1;
+                }
+            }
+        }
+        TagBrowser {
+            tags: counts.into_iter().collect(),
+            idx: 0,
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        self.idx = self.idx.saturating_sub(1);
+    }
+
+    pub fn move_down(&mut self) {
+        if self.idx + 1 < self.tags.len() {
+            self.idx += 1;
+        }
+    }
+
+    pub fn selected_tag(&self) -> Option<&str> {
+        self.tags.get(self.idx).map(|(tag, _)| tag.as_str())
+    }
+}
+
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Clear, List, ListItem, ListState};
+
+impl Widget for &TagBrowser {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let popup = area.centered(Constraint::Max(40), Constraint::Max(20));
+        let items = self.tags.iter().map(|(tag, count)| {
+            ListItem::new(Line::from(vec![
+                Span::raw(format!(" #{}", tag)).magenta(),
+                Span::raw(format!(" ({})", count)),
+            ]))
+        });
+        let block = Block::bordered()
+            .title(Line::from(" Tags ").centered())
+            .title_bottom(Line::from(" enter: filter, esc: close ").centered())
+            .border_style(Color::Yellow);
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::default().black().on_yellow());
+        let mut state = ListState::default().with_selected(Some(self.idx));
+        Clear.render(popup, buf);
+        StatefulWidget::render(list, popup, buf, &mut state);
+    }
+}
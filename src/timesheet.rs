@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::todolist::TodoList;
+
+// A stretch of time spent on a todo, recorded when its timer is stopped
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct TimeEntry {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+}
+
+impl TimeEntry {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+// `1h 05m`, or `12m` below an hour
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes();
+    if minutes >= 60 {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+// `1:02:03`, for the running timer
+pub fn format_clock(duration: Duration) -> String {
+    let seconds = duration.num_seconds().max(0);
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+// Time tracked on every day, split by todolist
+pub struct TimesheetDay {
+    pub date: NaiveDate,
+    pub lists: Vec<(String, Duration)>,
+    pub total: Duration,
+}
+
+// Popup reporting the tracked time of every todolist, archived todos included
+#[derive(Default)]
+pub struct Timesheet {
+    pub days: Vec<TimesheetDay>,
+    pub scroll: u16,
+}
+
+impl Timesheet {
+    // Entries count towards the day they started on, newest day first
+    pub fn new(todolists: &[TodoList]) -> Timesheet {
+        let now = Local::now();
+        let mut days: BTreeMap<NaiveDate, Vec<(String, Duration)>> = BTreeMap::new();
+        for todolist in todolists {
+            let todos = todolist.todos().iter()
+                .chain(todolist.archive.iter().map(|archived| &archived.todo));
+            for entry in todos.flat_map(|todo| todo.tracked(now)) {
+                let lists = days.entry(entry.start.date_naive()).or_default();
+                match lists.iter_mut().find(|(title, _)| *title == todolist.title) {
+                    Some((_, duration)) => *duration += entry.duration(),
+                    None => lists.push((todolist.title.clone(), entry.duration())),
+                }
+            }
+        }
+        let days = days.into_iter().rev().map(|(date, lists)| {
+            let total = lists.iter().map(|(_, duration)| *duration).sum();
+            TimesheetDay { date, lists, total }
+        });
+        Timesheet {
+            days: days.collect(),
+            scroll: 0,
+        }
+    }
+
+    pub fn scroll_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+
+    pub fn scroll_down(&mut self) {
+        let lines = self.days.iter().map(|day| day.lists.len() + 1).sum::<usize>();
+        if (self.scroll as usize) + 1 < lines {
+            self.scroll += 1;
+        }
+    }
+}
+
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Clear, Paragraph};
+
+impl Widget for &Timesheet {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let popup = area.centered(Constraint::Max(60), Constraint::Percentage(80));
+        let mut lines = Vec::new();
+        for day in &self.days {
+            lines.push(Line::from(vec![
+                Span::raw(format!(" {} ", day.date.format("%a %Y-%m-%d"))).bold(),
+                Span::raw(format_duration(day.total)).yellow(),
+            ]));
+            for (title, duration) in &day.lists {
+                lines.push(Line::from(vec![
+                    Span::raw(format!("   {} ", title)),
+                    Span::raw(format_duration(*duration)).dark_gray(),
+                ]));
+            }
+        }
+        if lines.is_empty() {
+            lines.push(Line::from(" No time tracked yet, press t on a todo to start a timer ").dark_gray());
+        }
+        let block = Block::bordered()
+            .title(Line::from(" Timesheet ").centered())
+            .title_bottom(Line::from(" j/k: scroll, esc: close ").centered())
+            .border_style(Color::Yellow);
+        let max_scroll = (lines.len() as u16).saturating_sub(popup.height.saturating_sub(2));
+        let timesheet = Paragraph::new(lines)
+            .scroll((self.scroll.min(max_scroll), 0))
+            .block(block);
+        Clear.render(popup, buf);
+        timesheet.render(popup, buf);
+    }
+}
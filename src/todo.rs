@@ -1,24 +1,575 @@
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-#[derive(Serialize, Deserialize)]
+use crate::dateparse;
+use crate::status::{self, Status};
+use crate::timesheet::{self, TimeEntry};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+pub fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// Makes sure ids handed out from now on are greater than `id`
+pub fn reserve_ids(id: u64) {
+    NEXT_ID.fetch_max(id + 1, Ordering::Relaxed);
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Todo {
+    // Stable identity across moves and lists, 0 until assigned for todos saved without one
+    #[serde(default)]
+    pub id: u64,
     pub value: String,
     pub selected: bool,
     pub completed: bool, 
+    // Name of the todo's status, see `status::status_of`
+    #[serde(default)]
+    pub status: String,
     pub editing: bool,
     pub description: String,
     pub todo_idx: usize,
+    #[serde(default)]
+    pub due: Option<Due>,
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    #[serde(default)]
+    pub priority: Priority,
+    // Todos saved before timestamps were tracked have none
+    #[serde(default)]
+    pub created_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Local>>,
+    // Nesting level, a todo is a subtask of the closest todo above it with a lower depth
+    #[serde(default)]
+    pub depth: usize,
+    // Hides the subtasks of this todo
+    #[serde(default)]
+    pub collapsed: bool,
+    // Ids of the todos that have to be finished before this one
+    #[serde(default)]
+    pub blocked_by: Vec<u64>,
+    #[serde(default)]
+    pub time_entries: Vec<TimeEntry>,
+    // When the running timer was started, only one todo has one at a time
+    #[serde(default)]
+    pub timer: Option<DateTime<Local>>,
+    // When each pomodoro spent on this todo was finished
+    #[serde(default)]
+    pub pomodoros: Vec<DateTime<Local>>,
+    #[serde(default)]
+    pub estimate: Option<Estimate>,
+    // Id of the occurrence created when this recurring todo was finished
+    #[serde(default)]
+    pub spawned: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum Priority {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl Priority {
+    pub fn raise(self) -> Priority {
+        match self {
+            Priority::None => Priority::Low,
+            Priority::Low => Priority::Medium,
+            Priority::Medium | Priority::High => Priority::High,
+        }
+    }
+
+    pub fn lower(self) -> Priority {
+        match self {
+            Priority::None | Priority::Low => Priority::None,
+            Priority::Medium => Priority::Low,
+            Priority::High => Priority::Medium,
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Priority::None => write!(f, "none"),
+            Priority::Low => write!(f, "low"),
+            Priority::Medium => write!(f, "medium"),
+            Priority::High => write!(f, "high"),
+        }
+    }
+}
+
+// The name of a `#tag` word, ignoring trailing punctuation
+pub fn tag_name(word: &str) -> Option<&str> {
+    let tag = word.strip_prefix('#')?.trim_end_matches(|c: char| c.is_ascii_punctuation());
+    (!tag.is_empty()).then_some(tag)
+}
+
+// When a todo has to be done by, the time of day is optional
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Due {
+    pub date: NaiveDate,
+    pub time: Option<NaiveTime>,
+}
+
+// How a todo repeats, a new occurrence is created each time it is completed
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum Recurrence {
+    Daily,
+    Weekly(Vec<Weekday>),
+    Monthly,
+    // Every N days counted from when the todo was completed
+    AfterCompletion(u32),
+}
+
+// Expected effort for a todo, either time or story points
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Estimate {
+    Minutes(u32),
+    Points(u32),
+}
+
+// Estimates of several todos added up, time and points are kept apart
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Effort {
+    pub minutes: u32,
+    pub points: u32,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Urgency {
+    Overdue,
+    Today,
+    Upcoming,
+}
+
+impl Due {
+    // Parses dates like `tomorrow 9am` or `2026-11-02 14:00`, see `dateparse`
+    pub fn parse(input: &str) -> Result<Due, String> {
+        dateparse::parse(input, Local::now().naive_local())
+    }
+
+    // The moment the todo becomes overdue, the end of the day if no time is set
+    pub fn deadline(&self) -> NaiveDateTime {
+        match self.time {
+            Some(time) => self.date.and_time(time),
+            None => self.date.and_time(NaiveTime::from_hms_opt(23, 59, 59).unwrap()),
+        }
+    }
+
+    pub fn urgency(&self, now: NaiveDateTime) -> Urgency {
+        if self.deadline() < now {
+            Urgency::Overdue
+        } else if self.date == now.date() {
+            Urgency::Today
+        } else {
+            Urgency::Upcoming
+        }
+    }
+}
+
+impl Recurrence {
+    // Parses `day`, `week`, `mon,thu`, `month` or `3 days`
+    pub fn parse(input: &str) -> Result<Recurrence, String> {
+        let input = input.trim().to_lowercase();
+        let words: Vec<&str> = input.split(|c: char| c == ',' || c.is_whitespace()).filter(|word| !word.is_empty()).collect();
+        match words.as_slice() {
+            ["day" | "daily"] => Ok(Recurrence::Daily),
+            ["week" | "weekly"] => Ok(Recurrence::Weekly(Vec::new())),
+            ["month" | "monthly"] => Ok(Recurrence::Monthly),
+            [amount, "day" | "days" | "d"] => {
+                let days = amount.parse().map_err(|_| format!("invalid amount: {}", amount))?;
+                Ok(Recurrence::AfterCompletion(days))
+            }
+            days => {
+                let mut weekdays = days.iter().map(|day| dateparse::parse_weekday(day)).collect::<Result<Vec<_>, _>>()?;
+                weekdays.sort_by_key(|day| day.num_days_from_monday());
+                weekdays.dedup();
+                Ok(Recurrence::Weekly(weekdays))
+            }
+        }
+    }
+
+    // Due date of the occurrence following one due at `due`, skipping occurrences
+    // already in the past so an overdue todo doesn't come back overdue.
+    // None once the dates run past what can be represented
+    pub fn next_due(&self, due: Option<Due>, today: NaiveDate) -> Option<Due> {
+        let time = due.and_then(|due| due.time);
+        let base = due.map_or(today, |due| due.date);
+        let date = match self {
+            Recurrence::AfterCompletion(days) => today.checked_add_days(Days::new(*days as u64))?,
+            // Counted from the base so the 31st doesn't drift to the 28th after February
+            Recurrence::Monthly => {
+                let mut months = 1;
+                loop {
+                    let date = base.checked_add_months(Months::new(months))?;
+                    if date >= today {
+                        break date;
+                    }
+                    months += 1;
+                }
+            }
+            _ => {
+                let mut date = self.step(base)?;
+                while date < today {
+                    date = self.step(date)?;
+                }
+                date
+            }
+        };
+        Some(Due { date, time })
+    }
+
+    // The next daily or weekly occurrence after `date`
+    fn step(&self, date: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Recurrence::Weekly(days) if days.is_empty() => date.checked_add_days(Days::new(7)),
+            Recurrence::Weekly(days) => {
+                (1..=7).map_while(|offset| date.checked_add_days(Days::new(offset)))
+                    .find(|date| days.contains(&date.weekday()))
+            }
+            _ => date.checked_add_days(Days::new(1)),
+        }
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekly(days) if days.is_empty() => write!(f, "weekly"),
+            Recurrence::Weekly(days) => {
+                let days: Vec<String> = days.iter().map(|day| day.to_string().to_lowercase()).collect();
+                write!(f, "{}", days.join(","))
+            }
+            Recurrence::Monthly => write!(f, "monthly"),
+            Recurrence::AfterCompletion(days) => write!(f, "every {}d", days),
+        }
+    }
+}
+
+// Largest estimates accepted, well beyond anything a single todo takes
+const MAX_ESTIMATE_MINUTES: f32 = 1000.0 * 8.0 * 60.0;
+const MAX_ESTIMATE_POINTS: u32 = 1000;
+
+impl Estimate {
+    // Parses `30m`, `2h`, `1h30m`, `1.5h`, or `3pt` and bare numbers as story points,
+    // up to a thousand days or points
+    pub fn parse(input: &str) -> Result<Estimate, String> {
+        let input: String = input.to_lowercase().split_whitespace().collect();
+        let points = ["points", "pts", "pt", "p"].iter().find_map(|unit| input.strip_suffix(unit));
+        if let Some(points) = points.or(input.chars().all(|c| c.is_ascii_digit()).then_some(input.as_str())) {
+            let points: u32 = points.parse().map_err(|_| format!("invalid estimate: {}", input))?;
+            if points > MAX_ESTIMATE_POINTS {
+                return Err(format!("estimate too large: {}", input));
+            }
+            return Ok(Estimate::Points(points));
+        }
+        let mut minutes = 0.0;
+        let mut rest = input.as_str();
+        while !rest.is_empty() {
+            let split = rest.find(|c: char| c.is_alphabetic()).ok_or(format!("missing unit: {}", input))?;
+            let amount: f32 = rest[..split].parse().map_err(|_| format!("invalid estimate: {}", input))?;
+            let unit_end = rest[split..].find(|c: char| !c.is_alphabetic()).map_or(rest.len(), |end| split + end);
+            minutes += match &rest[split..unit_end] {
+                "m" | "min" | "mins" => amount,
+                "h" | "hr" | "hrs" => amount * 60.0,
+                "d" | "day" | "days" => amount * 60.0 * 8.0,
+                unit => return Err(format!("unknown unit: {}", unit)),
+            };
+            rest = &rest[unit_end..];
+        }
+        if minutes <= 0.0 {
+            return Err(format!("invalid estimate: {}", input));
+        }
+        if minutes > MAX_ESTIMATE_MINUTES {
+            return Err(format!("estimate too large: {}", input));
+        }
+        Ok(Estimate::Minutes(minutes.round() as u32))
+    }
+}
+
+impl Effort {
+    pub fn add(&mut self, estimate: Estimate) {
+        match estimate {
+            // Saturating, estimates saved before they were capped can be anything
+            Estimate::Minutes(minutes) => self.minutes = self.minutes.saturating_add(minutes),
+            Estimate::Points(points) => self.points = self.points.saturating_add(points),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.minutes == 0 && self.points == 0
+    }
+}
+
+// `45m`, `2h` or `1h30m`
+fn format_minutes(minutes: u32) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, 0) => format!("{}h", hours),
+        (hours, minutes) => format!("{}h{:02}m", hours, minutes),
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Estimate::Minutes(minutes) => write!(f, "{}", format_minutes(*minutes)),
+            Estimate::Points(points) => write!(f, "{}pt", points),
+        }
+    }
+}
+
+impl fmt::Display for Effort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.minutes, self.points) {
+            (0, 0) => write!(f, "0"),
+            (minutes, 0) => write!(f, "{}", format_minutes(minutes)),
+            (0, points) => write!(f, "{}pt", points),
+            (minutes, points) => write!(f, "{} + {}pt", format_minutes(minutes), points),
+        }
+    }
+}
+
+impl fmt::Display for Due {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.date.format("%a %Y-%m-%d"))?;
+        if let Some(time) = self.time {
+            write!(f, " {}", time.format("%H:%M"))?;
+        }
+        Ok(())
+    }
 }
 
 impl Todo {
     pub fn new(idx: usize) -> Todo {
         Todo{
+            id: next_id(),
             selected: false,
             value: String::new(),
             completed: false,
+            status: String::new(),
             description: String::new(),
             editing: false,
             todo_idx: idx,
+            due: None,
+            recurrence: None,
+            priority: Priority::None,
+            created_at: Some(Local::now()),
+            updated_at: Some(Local::now()),
+            completed_at: None,
+            depth: 0,
+            collapsed: false,
+            blocked_by: Vec::new(),
+            time_entries: Vec::new(),
+            timer: None,
+            pomodoros: Vec::new(),
+            estimate: None,
+            spawned: None,
+        }
+    }
+
+    // Blocked by any todo that is still open, `open_ids` holds the ids of every open todo
+    pub fn is_blocked(&self, open_ids: &HashSet<u64>) -> bool {
+        self.blocked_by.iter().any(|id| open_ids.contains(id))
+    }
+
+    // Records that the todo was just changed
+    pub fn touch(&mut self) {
+        self.updated_at = Some(Local::now());
+    }
+
+    pub fn set_status(&mut self, status: &Status) {
+        if status.finished {
+            self.stop_timer();
         }
+        if self.completed != status.finished {
+            self.completed = status.finished;
+            self.completed_at = if self.completed { Some(Local::now()) } else { None };
+        }
+        self.status = status.name.clone();
+        self.touch();
+    }
+
+    // The next occurrence of a recurring todo, not yet completed. None if it doesn't recur
+    // or its next due date is out of range
+    pub fn next_occurrence(&self) -> Option<Todo> {
+        let recurrence = self.recurrence.as_ref()?;
+        let due = recurrence.next_due(self.due, Local::now().date_naive())?;
+        let mut todo = self.clone();
+        todo.id = next_id();
+        todo.blocked_by.clear();
+        todo.spawned = None;
+        todo.time_entries.clear();
+        todo.timer = None;
+        todo.pomodoros.clear();
+        todo.completed = false;
+        todo.status = String::new();
+        todo.editing = false;
+        todo.due = Some(due);
+        todo.created_at = Some(Local::now());
+        todo.updated_at = todo.created_at;
+        todo.completed_at = None;
+        Some(todo)
+    }
+
+    pub fn start_timer(&mut self) {
+        if self.timer.is_none() {
+            self.timer = Some(Local::now());
+        }
+    }
+
+    pub fn stop_timer(&mut self) {
+        if let Some(start) = self.timer.take() {
+            self.time_entries.push(TimeEntry { start, end: Local::now() });
+            self.touch();
+        }
+    }
+
+    // The recorded time entries, plus the running timer up to `now`
+    pub fn tracked(&self, now: DateTime<Local>) -> impl Iterator<Item = TimeEntry> + '_ {
+        let running = self.timer.map(|start| TimeEntry { start, end: now });
+        self.time_entries.iter().copied().chain(running)
+    }
+
+    pub fn time_spent(&self) -> chrono::Duration {
+        self.tracked(Local::now()).map(|entry| entry.duration()).sum()
+    }
+
+    // Moves a `due:<date>` token typed into the value onto the due date
+    pub fn extract_due(&mut self) {
+        let mut due = None;
+        let words: Vec<&str> = self.value.split(' ').filter(|word| {
+            match word.strip_prefix("due:").and_then(|date| Due::parse(date).ok()) {
+                Some(parsed) => {
+                    due = Some(parsed);
+                    false
+                }
+                None => true,
+            }
+        }).collect();
+        if due.is_some() {
+            self.value = words.join(" ");
+            self.due = due;
+            self.touch();
+        }
+    }
+
+    // Words in the value starting with `#`, without the `#`
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.value.split_whitespace().filter_map(tag_name)
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags().any(|own| own.eq_ignore_ascii_case(tag))
+    }
+
+    pub fn urgency(&self) -> Option<Urgency> {
+        self.due.map(|due| due.urgency(Local::now().naive_local()))
+    }
+}
+
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Paragraph, Wrap};
+
+use crate::settings::Settings;
+
+// Detail pane showing everything about a single todo
+pub struct TodoDetail<'a> {
+    pub todo: &'a Todo,
+    pub settings: &'a Settings,
+}
+
+impl Widget for TodoDetail<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let todo = self.todo;
+        let mut lines = vec![Line::from(todo.value.as_str()).bold()];
+        if let Some(due) = todo.due {
+            lines.push(Line::from(format!("Due: {}", due)));
+        }
+        if let Some(recurrence) = &todo.recurrence {
+            lines.push(Line::from(format!("Repeats: {}", recurrence)));
+        }
+        let status = status::status_of(&self.settings.statuses, todo);
+        lines.push(Line::from(format!("Status: {}", status.name)));
+        if let Some(estimate) = todo.estimate {
+            lines.push(Line::from(format!("Estimate: {}", estimate)));
+        }
+        if todo.priority != Priority::None {
+            lines.push(Line::from(format!("Priority: {}", todo.priority)));
+        }
+        if !todo.time_entries.is_empty() || todo.timer.is_some() {
+            let running = if todo.timer.is_some() { " (running)" } else { "" };
+            lines.push(Line::from(format!("Time: {}{}", timesheet::format_duration(todo.time_spent()), running)));
+        }
+        if !todo.pomodoros.is_empty() {
+            lines.push(Line::from(format!("Pomodoros: {}", todo.pomodoros.len())));
+        }
+        if self.settings.timestamps {
+            let timestamps = [
+                ("Created", todo.created_at),
+                ("Updated", todo.updated_at),
+                ("Completed", todo.completed_at),
+            ];
+            for (label, timestamp) in timestamps {
+                if let Some(timestamp) = timestamp {
+                    let line = format!("{}: {}", label, timestamp.format("%Y-%m-%d %H:%M"));
+                    lines.push(Line::from(line).dark_gray());
+                }
+            }
+        }
+        if !todo.description.is_empty() {
+            lines.push(Line::raw(""));
+            lines.extend(todo.description.lines().map(Line::raw));
+        }
+        let detail = Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(" Details "));
+        detail.render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates() {
+        assert_eq!(Estimate::parse("1h30m"), Ok(Estimate::Minutes(90)));
+        assert_eq!(Estimate::parse("1.5h"), Ok(Estimate::Minutes(90)));
+        assert_eq!(Estimate::parse("2d"), Ok(Estimate::Minutes(960)));
+        assert_eq!(Estimate::parse("3pt"), Ok(Estimate::Points(3)));
+        assert_eq!(Estimate::parse("5"), Ok(Estimate::Points(5)));
+        assert!(Estimate::parse("0m").is_err());
+        assert!(Estimate::parse("3x").is_err());
+    }
+
+    #[test]
+    fn estimates_are_capped() {
+        assert_eq!(Estimate::parse("1000d"), Ok(Estimate::Minutes(480_000)));
+        assert!(Estimate::parse("1001d").is_err());
+        assert!(Estimate::parse("4000000000").is_err());
+        assert!(Estimate::parse("4000000000m").is_err());
+        assert!(Estimate::parse("99999999999pt").is_err());
+    }
+
+    #[test]
+    fn effort_saturates() {
+        let mut effort = Effort::default();
+        effort.add(Estimate::Minutes(u32::MAX));
+        effort.add(Estimate::Minutes(u32::MAX));
+        effort.add(Estimate::Points(u32::MAX));
+        effort.add(Estimate::Points(1));
+        assert_eq!(effort, Effort { minutes: u32::MAX, points: u32::MAX });
     }
 }
@@ -1,7 +1,13 @@
+use chrono::Local;
 use serde::{Deserialize, Serialize};
-use crate::todo::Todo;
+use crate::todo::{self, Due, Effort, Estimate, Priority, Recurrence, Todo, Urgency};
 use crate::app::Mode;
-use std::cmp::{min, max};
+use crate::archive::ArchivedTodo;
+use crate::settings::Settings;
+use crate::status::{self, Status};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::cmp::{min, max, Ordering};
 
 
 #[derive(Serialize, Deserialize)]
@@ -41,6 +47,42 @@ pub struct TodoList {
     visual_range: VisualRange,
     pub editing_title: bool,
     pub selected: bool,
+    // Index of the first rendered todo, kept in sync by the widget
+    #[serde(skip)]
+    scroll_offset: Cell<usize>,
+    // Number of rows available to todos the last time the list was drawn
+    #[serde(skip)]
+    viewport_height: Cell<usize>,
+    // Where the list and each visible todo were last drawn, used for mouse hit testing
+    #[serde(skip)]
+    area: Cell<Rect>,
+    #[serde(skip)]
+    todo_areas: RefCell<Vec<(usize, Rect)>>,
+    // Hides completed todos in this list only
+    #[serde(default)]
+    pub hide_completed: bool,
+    // Completed todos put away by `:clean`
+    #[serde(default)]
+    pub archive: Vec<ArchivedTodo>,
+    // App wide options for which todos are shown, pushed down by the app
+    #[serde(skip)]
+    pub visibility: Visibility,
+}
+
+// Which todos are shown and can be selected, shared by every todolist
+#[derive(Clone, Default)]
+pub struct Visibility {
+    // Only todos with this tag are shown
+    pub tag: Option<String>,
+    pub hide_completed: bool,
+}
+
+// What part of a todolist lies under the mouse
+pub enum ListHit {
+    Title,
+    Todo(usize),
+    Checkbox(usize),
+    Below,
 }
 
 impl TodoList{
@@ -52,24 +94,341 @@ impl TodoList{
             visual_range: VisualRange::new(0, 0),
             editing_title: false,
             selected: false,
+            scroll_offset: Cell::new(0),
+            viewport_height: Cell::new(0),
+            area: Cell::new(Rect::default()),
+            todo_areas: RefCell::new(Vec::new()),
+            hide_completed: false,
+            archive: Vec::new(),
+            visibility: Visibility::default(),
+        }
+    }
+
+    ////////////////
+    // Visibility
+    ////////////////
+
+    // Hidden todos are skipped when drawing and moving the selection
+    pub fn is_visible(&self, idx: usize) -> bool {
+        let todo = &self.todos[idx];
+        if self.has_collapsed_ancestor(idx) {
+            return false;
+        }
+        if todo.completed && (self.hide_completed || self.visibility.hide_completed) {
+            return false;
+        }
+        match &self.visibility.tag {
+            Some(tag) => todo.has_tag(tag),
+            None => true,
+        }
+    }
+
+    pub fn toggle_hide_completed(&mut self, mode: Mode) {
+        assert!(mode == Mode::Normal);
+        self.hide_completed ^= true;
+        self.reselect();
+    }
+
+    // Moves the selection off the selected todo if it has just been hidden
+    pub fn reselect(&mut self) {
+        if let Some(idx) = self.todo_idx {
+            if !self.is_visible(idx) {
+                self.unselect_todo(idx);
+                self.select_todo(idx);
+            }
+        }
+    }
+
+    fn next_visible(&self, idx: usize) -> Option<usize> {
+        (idx + 1..self.todos.len()).find(|&i| self.is_visible(i))
+    }
+
+    fn prev_visible(&self, idx: usize) -> Option<usize> {
+        (0..idx).rev().find(|&i| self.is_visible(i))
+    }
+
+    // `idx` itself if visible, otherwise the closest visible todo below or above it
+    fn nearest_visible(&self, idx: usize) -> Option<usize> {
+        if idx < self.todos.len() && self.is_visible(idx) {
+            return Some(idx);
+        }
+        self.next_visible(idx).or_else(|| self.prev_visible(idx))
+    }
+
+    // Visible todos in the visual selection
+    fn visual_selection(&self) -> Vec<usize> {
+        let lower = self.visual_range.lower();
+        let upper = min(self.visual_range.uppser(), self.todos.len().saturating_sub(1));
+        (lower..=upper).filter(|&i| self.is_visible(i)).collect()
+    }
+
+    ////////////////
+    // Subtasks
+    ////////////////
+
+    // Closest visible todo above at the same depth, stopping at the parent
+    fn prev_sibling(&self, idx: usize) -> Option<usize> {
+        let depth = self.todos[idx].depth;
+        for i in (0..idx).rev() {
+            if self.todos[i].depth < depth {
+                return None;
+            }
+            if self.todos[i].depth == depth && self.is_visible(i) {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    // Closest visible todo below at the same depth, stopping at the end of the parent
+    fn next_sibling(&self, idx: usize) -> Option<usize> {
+        let depth = self.todos[idx].depth;
+        for i in self.subtree_end(idx)..self.todos.len() {
+            if self.todos[i].depth < depth {
+                return None;
+            }
+            if self.todos[i].depth == depth && self.is_visible(i) {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    fn has_collapsed_ancestor(&self, idx: usize) -> bool {
+        let mut depth = self.todos[idx].depth;
+        for todo in self.todos[..idx].iter().rev() {
+            if depth == 0 {
+                break;
+            }
+            if todo.depth < depth {
+                if todo.collapsed {
+                    return true;
+                }
+                depth = todo.depth;
+            }
+        }
+        false
+    }
+
+    // One past the last subtask of the todo at `idx`
+    pub fn subtree_end(&self, idx: usize) -> usize {
+        let depth = self.todos[idx].depth;
+        (idx + 1..self.todos.len())
+            .find(|&i| self.todos[i].depth <= depth)
+            .unwrap_or(self.todos.len())
+    }
+
+    pub fn has_subtasks(&self, idx: usize) -> bool {
+        self.subtree_end(idx) > idx + 1
+    }
+
+    // Completed and total number of subtasks at any depth below the todo at `idx`
+    pub fn subtask_progress(&self, idx: usize) -> (usize, usize) {
+        let subtasks = &self.todos[idx + 1..self.subtree_end(idx)];
+        let completed = subtasks.iter().filter(|todo| todo.completed).count();
+        (completed, subtasks.len())
+    }
+
+    // For each level of nesting of the todo at `idx`, whether more todos follow at that level,
+    // used to draw the tree guides
+    fn tree_guides(&self, idx: usize) -> Vec<bool> {
+        (1..=self.todos[idx].depth).map(|level| {
+            self.todos[idx + 1..].iter()
+                .find(|todo| todo.depth <= level)
+                .is_some_and(|todo| todo.depth == level)
+        }).collect()
+    }
+
+    // Keeps every todo at most one level deeper than the todo above it
+    fn normalize_depths(&mut self) {
+        let mut max_depth = 0;
+        for todo in &mut self.todos {
+            todo.depth = min(todo.depth, max_depth);
+            max_depth = todo.depth + 1;
+        }
+    }
+
+    fn reindex(&mut self) {
+        for (i, todo) in self.todos.iter_mut().enumerate() {
+            todo.todo_idx = i;
+        }
+    }
+
+    // Makes the selected todo and its subtasks a subtask of the todo above it
+    pub fn indent(&mut self, mode: Mode) {
+        assert!(mode == Mode::Normal);
+        let Some(idx) = self.todo_idx else {
+            return;
+        };
+        let Some(prev) = self.prev_visible(idx) else {
+            return;
+        };
+        if self.todos[idx].depth <= self.todos[prev].depth {
+            let end = self.subtree_end(idx);
+            for todo in &mut self.todos[idx..end] {
+                todo.depth += 1;
+            }
+            // The new parent must be expanded for its subtask to stay visible
+            let parent = (0..idx).rev().find(|&i| self.todos[i].depth < self.todos[idx].depth);
+            if let Some(parent) = parent {
+                self.todos[parent].collapsed = false;
+            }
+        }
+    }
+
+    pub fn outdent(&mut self, mode: Mode) {
+        assert!(mode == Mode::Normal);
+        let Some(idx) = self.todo_idx else {
+            return;
+        };
+        if self.todos[idx].depth > 0 {
+            let end = self.subtree_end(idx);
+            for todo in &mut self.todos[idx..end] {
+                todo.depth -= 1;
+            }
+        }
+    }
+
+    // Returns whether the selected todo had subtasks to fold
+    pub fn toggle_collapsed(&mut self, mode: Mode) -> bool {
+        assert!(mode == Mode::Normal);
+        match self.todo_idx {
+            Some(idx) if self.has_subtasks(idx) => {
+                self.todos[idx].collapsed ^= true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Returns the number of open todos and the total number of todos
+    pub fn counts(&self) -> (usize, usize) {
+        let open = self.todos.iter().filter(|todo| !todo.completed).count();
+        (open, self.todos.len())
+    }
+
+    // Summed estimates of the open and of the completed todos
+    pub fn effort(&self) -> (Effort, Effort) {
+        let mut open = Effort::default();
+        let mut completed = Effort::default();
+        for todo in &self.todos {
+            if let Some(estimate) = todo.estimate {
+                if todo.completed { completed.add(estimate) } else { open.add(estimate) }
+            }
+        }
+        (open, completed)
+    }
+
+    pub fn selected_todo(&self) -> Option<&Todo> {
+        self.todo_idx.map(|idx| &self.todos[idx])
+    }
+
+    pub fn todos(&self) -> &[Todo] {
+        &self.todos
+    }
+
+    pub fn todos_mut(&mut self) -> &mut [Todo] {
+        &mut self.todos
+    }
+
+    pub fn len(&self) -> usize {
+        self.todos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.todos.is_empty()
+    }
+
+    // Takes a todo and its subtasks out of the list, keeping the selection in bounds
+    pub fn remove_subtree(&mut self, idx: usize) -> Vec<Todo> {
+        let end = self.subtree_end(idx);
+        let mut todos: Vec<Todo> = self.todos.drain(idx..end).collect();
+        for todo in &mut todos {
+            todo.selected = false;
+        }
+        self.reindex();
+        if self.todo_idx.is_some_and(|selected| selected >= self.todos.len()) {
+            self.todo_idx = self.todos.len().checked_sub(1);
         }
+        todos
     }
 
-    fn current_todo(&mut self) -> Option<&mut Todo> {
+    // Puts a todo taken out by `remove_subtree` back in at `depth`, its subtasks keep
+    // their depth below it
+    pub fn insert_subtree(&mut self, idx: usize, depth: usize, mut todos: Vec<Todo>) -> usize {
+        let pos = min(idx, self.todos.len());
+        let root = todos.first().map_or(0, |todo| todo.depth);
+        for todo in &mut todos {
+            todo.depth = todo.depth - root + depth;
+        }
+        self.todos.splice(pos..pos, todos);
+        self.normalize_depths();
+        self.reindex();
+        pos
+    }
+
+    pub fn insert_todo(&mut self, idx: usize, todo: Todo) -> usize {
+        let pos = min(idx, self.todos.len());
+        self.todos.insert(pos, todo);
+        self.normalize_depths();
+        self.reindex();
+        pos
+    }
+
+    // Finds what was drawn at a screen position during the last render
+    pub fn hit_test(&self, column: u16, row: u16) -> Option<ListHit> {
+        let area = self.area.get();
+        let position = Position::new(column, row);
+        if !area.contains(position) {
+            return None;
+        }
+        if row == area.y {
+            return Some(ListHit::Title);
+        }
+        for (idx, todo_area) in self.todo_areas.borrow().iter() {
+            if todo_area.contains(position) {
+                // The checkbox is the ` [ ] ` prefix on the first line of a todo, after the tree guides
+                let checkbox_x = todo_area.x + 2 * self.todos[*idx].depth as u16;
+                let on_checkbox = row == todo_area.y && (checkbox_x..checkbox_x + 5).contains(&column);
+                if on_checkbox {
+                    return Some(ListHit::Checkbox(*idx));
+                }
+                return Some(ListHit::Todo(*idx));
+            }
+        }
+        Some(ListHit::Below)
+    }
+
+    pub fn current_todo(&mut self) -> Option<&mut Todo> {
         if let Some(idx) = self.todo_idx {
             return Some(&mut self.todos[idx]);
         }
         return None;
     }
     
+    // New todos go below the selected todo as its sibling, or as its first subtask
+    // if its subtasks are expanded
     pub fn create_todo_below(&mut self) -> usize {
         let len = self.todos.len();
         let mut pos = 0;
+        let mut depth = 0;
         if let Some(idx) = self.todo_idx {
             self.unselect_todo(idx);
-            pos = idx + 1;
+            let todo = &self.todos[idx];
+            if todo.collapsed {
+                pos = self.subtree_end(idx);
+                depth = todo.depth;
+            } else {
+                pos = idx + 1;
+                depth = if self.has_subtasks(idx) { todo.depth + 1 } else { todo.depth };
+            }
+        }
+        let mut todo = Todo::new(len);
+        todo.depth = depth;
+        // New todos carry the filtered tag so they don't vanish
+        if let Some(tag) = &self.visibility.tag {
+            todo.value = format!("#{} ", tag);
         }
-        let todo = Todo::new(len);
         self.todos.push(todo);
         self.move_todo(len, pos);
         return pos;
@@ -104,9 +463,10 @@ impl TodoList{
             self.todo_idx = None;
             return;
         }
-        let new_idx = min(idx, len - 1);
-        self.todo_idx = Some(new_idx);
-        self.todos[new_idx].selected = true;
+        self.todo_idx = self.nearest_visible(min(idx, len - 1));
+        if let Some(new_idx) = self.todo_idx {
+            self.todos[new_idx].selected = true;
+        }
     }
 
     pub fn unselect_todo(&mut self, idx: usize) {
@@ -131,6 +491,7 @@ impl TodoList{
                 self.swap_todo(i, i+1);
             }
         }
+        self.normalize_depths();
     }
 
     pub fn move_selection_up(&mut self, mode: Mode) {
@@ -139,17 +500,15 @@ impl TodoList{
             Mode::Normal => {
                 if let Some(idx) = self.todo_idx {
                     self.unselect_todo(idx);
-                    if idx > 0 {
-                        self.select_todo(idx.saturating_sub(1));
-                    }
-                    else {
-                        self.todo_idx = None;
+                    match self.prev_visible(idx) {
+                        Some(new_idx) => self.select_todo(new_idx),
+                        None => self.todo_idx = None,
                     }
                 }
             }
             Mode::Visual => {
                 let idx = self.todo_idx.expect("todo must be selected in visual mode");
-                let new_idx = idx.saturating_sub(1);
+                let new_idx = self.prev_visible(idx).unwrap_or(idx);
                 self.visual_range.end = new_idx;
                 self.select_todo(new_idx);
                 if !self.visual_range.contains(idx) {
@@ -161,12 +520,11 @@ impl TodoList{
     }
 
     pub fn move_selection_down(&mut self, mode: Mode) {
-        let len = self.todos.len();
         match mode {
             Mode::Normal => {
                 if let Some(idx) = self.todo_idx {
                     self.unselect_todo(idx);
-                    self.select_todo(min(idx + 1, len - 1));
+                    self.select_todo(self.next_visible(idx).unwrap_or(idx));
                 }
                 else {
                     self.select_todo(0);
@@ -174,7 +532,7 @@ impl TodoList{
             }
             Mode::Visual => {
                 let idx = self.todo_idx.expect("a todo must be selected in visual mode");
-                let new_idx = min(idx + 1, len - 1);
+                let new_idx = self.next_visible(idx).unwrap_or(idx);
                 self.visual_range.end = new_idx;
                 self.select_todo(new_idx);
                 if !self.visual_range.contains(idx) {
@@ -185,80 +543,164 @@ impl TodoList{
         }
     }
 
-    pub fn move_todo_up(&mut self, mode: Mode) {
+    fn half_page(&self) -> usize {
+        max(self.viewport_height.get() / 2, 1)
+    }
+
+    pub fn scroll_half_page_up(&mut self, mode: Mode) {
+        for _ in 0..self.half_page() {
+            if self.todo_idx.and_then(|idx| self.prev_visible(idx)).is_none() {
+                break;
+            }
+            self.move_selection_up(mode);
+        }
+    }
+
+    pub fn scroll_half_page_down(&mut self, mode: Mode) {
+        for _ in 0..self.half_page() {
+            self.move_selection_down(mode);
+        }
+    }
+
+    // Todos only swap places with visible neighbours, along with their subtasks. Hidden
+    // todos between them stay in between. Returns whether anything moved
+    pub fn move_todo_up(&mut self, mode: Mode) -> bool {
         match mode {
             Mode::Normal => {
                 if let Some(idx) = self.todo_idx {
-                    self.swap_todo(idx, idx.saturating_sub(1));
-                    self.select_todo(idx.saturating_sub(1));
+                    if let Some(prev) = self.prev_sibling(idx) {
+                        let end = self.subtree_end(idx);
+                        self.swap_subtrees(prev, idx, end);
+                        self.todo_idx = Some(prev);
+                        return true;
+                    }
                 }
+                false
             }
             Mode::Visual => {
                 let lower = self.visual_range.lower();
                 let upper = self.visual_range.uppser();
-                if lower > 0 {
-                    self.move_todo(lower - 1, upper);
+                if let Some(prev) = self.prev_visible(lower) {
+                    self.move_todo(prev, upper);
                     self.visual_range.start -= 1;
                     self.visual_range.end -= 1;
+                    self.todo_idx = Some(self.visual_range.end);
+                    return true;
                 }
+                false
             }
             _ => unreachable!("move only supports Normal and Visual Mode")
         }
     }
 
-    pub fn move_todo_down(&mut self, mode: Mode) {
-        let len = self.todos.len();
+    pub fn move_todo_down(&mut self, mode: Mode) -> bool {
         match mode {
             Mode::Normal => {
                 if let Some(idx) = self.todo_idx {
-                    if idx < (self.todos.len() - 1) {
-                        self.swap_todo(idx, min(idx + 1, len - 1));
-                        self.select_todo(min(idx + 1, len - 1));
+                    let end = self.subtree_end(idx);
+                    if let Some(next) = self.next_sibling(idx) {
+                        let next_end = self.subtree_end(next);
+                        self.swap_subtrees(idx, next, next_end);
+                        self.todo_idx = Some(next_end - (end - idx));
+                        return true;
                     }
                 }
+                false
             }
             Mode::Visual => {
                 let lower = self.visual_range.lower();
                 let upper = self.visual_range.uppser();
-                if upper < len - 1 {
-                    self.move_todo(upper + 1, lower);
+                if let Some(next) = self.next_visible(upper) {
+                    self.move_todo(next, lower);
                     self.visual_range.start += 1;
                     self.visual_range.end += 1;
+                    self.todo_idx = Some(self.visual_range.end);
+                    return true;
                 }
+                false
             }
             _ => unreachable!("move only supports Normal and Visual Mode")
         }
     }
 
+    // Swaps the sibling subtrees starting at `first` and `second`, the latter ending at `end`.
+    // Whatever lies between the first subtree and the second stays there
+    fn swap_subtrees(&mut self, first: usize, second: usize, end: usize) {
+        let first_end = self.subtree_end(first);
+        let mut todos: Vec<Todo> = self.todos.drain(first..end).collect();
+        let second_todos = todos.split_off(second - first);
+        let between = todos.split_off(first_end - first);
+        let swapped = second_todos.into_iter().chain(between).chain(todos);
+        self.todos.splice(first..first, swapped);
+        self.reindex();
+    }
+
     pub fn delete_todo(&mut self, mode: Mode) {
         match mode {
             Mode::Normal => {
                 if let Some(idx) = self.todo_idx {
-                    self.todos.remove(idx);
+                    let end = self.subtree_end(idx);
+                    self.todos.drain(idx..end);
+                    self.reindex();
                     self.select_todo(idx);
                 }
             }
             Mode::Visual => {
                 let lower = self.visual_range.lower();
-                let upper = self.visual_range.uppser();
-                assert!(upper < self.todos.len());
-                for i in (lower..=upper).rev() {
-                    self.todos.remove(i);
+                // Backwards so subtasks are removed before their parents
+                for i in self.visual_selection().into_iter().rev() {
+                    if i < self.todos.len() {
+                        let end = self.subtree_end(i);
+                        self.todos.drain(i..end);
+                    }
                 }
+                self.normalize_depths();
+                self.reindex();
                 self.select_todo(lower);
             }
             _ => unreachable!("delete only support Normal and Visual Mode")
         }
     }
 
-    pub fn delete_completed_todos(&mut self, mode: Mode) {
+    // Moves completed todos into the archive, returns how many were moved.
+    // A completed todo with open subtasks stays so they keep their parent
+    pub fn archive_completed_todos(&mut self, mode: Mode) -> usize {
         assert!(mode == Mode::Command);
+        let now = Local::now();
         let len = self.todos.len();
+        let mut archived = 0;
+        // Backwards so completed subtasks are gone by the time their parent is looked at
         for i in (0..len).rev() {
-            if self.todos[i].completed {
-                self.todos.remove(i);
+            if self.todos[i].completed && !self.has_subtasks(i) {
+                let mut todo = self.todos.remove(i);
+                todo.selected = false;
+                let completed_at = todo.completed_at.unwrap_or(now);
+                self.archive.push(ArchivedTodo { todo, completed_at });
+                archived += 1;
             }
         }
+        if self.todo_idx.is_some_and(|idx| idx >= self.todos.len()) {
+            self.todo_idx = self.todos.len().checked_sub(1);
+        }
+        self.normalize_depths();
+        self.reindex();
+        archived
+    }
+
+    // Puts an archived todo back at the end of the list as an open todo
+    pub fn restore_archived(&mut self, archive_idx: usize) {
+        let mut todo = self.archive.remove(archive_idx).todo;
+        todo.completed = false;
+        todo.completed_at = None;
+        todo.depth = 0;
+        todo.touch();
+        todo.todo_idx = self.todos.len();
+        self.todos.push(todo);
+    }
+
+    // Returns how many archived todos were dropped
+    pub fn purge_archive(&mut self) -> usize {
+        self.archive.drain(..).count()
     }
 
     pub fn start_visual_selection(&mut self, mode: Mode) {
@@ -299,24 +741,182 @@ impl TodoList{
         else {
             let todo = self.current_todo().expect("a todo must be select in insert mode if the todolist title isn't");
             todo.editing = false;
+            todo.extract_due();
+        }
+    }
+
+    // Finishes open todos and reopens finished ones.
+    // `open_ids` holds the ids of every open todo, blocked todos can't be finished.
+    // Returns how many todos changed and how many were left alone for being blocked
+    pub fn toggle_completed(&mut self, mode: Mode, statuses: &[Status], open_ids: &HashSet<u64>) -> (usize, usize) {
+        self.change_status(mode, open_ids, |todo| status::first(statuses, !todo.completed).clone())
+    }
+
+    pub fn cycle_status(&mut self, mode: Mode, statuses: &[Status], open_ids: &HashSet<u64>) -> (usize, usize) {
+        self.change_status(mode, open_ids, |todo| status::next(statuses, todo).clone())
+    }
+
+    pub fn set_status(&mut self, mode: Mode, status: &Status, open_ids: &HashSet<u64>) -> (usize, usize) {
+        self.change_status(mode, open_ids, |_| status.clone())
+    }
+
+    fn change_status<F>(&mut self, mode: Mode, open_ids: &HashSet<u64>, pick: F) -> (usize, usize)
+    where
+        F: Fn(&Todo) -> Status,
+    {
+        let allowed = |todo: &Todo, status: &Status| !status.finished || !todo.is_blocked(open_ids);
+        let mut changed = 0;
+        let mut blocked = 0;
+        match mode {
+            Mode::Normal => {
+                if let Some(idx) = self.todo_idx {
+                    let status = pick(&self.todos[idx]);
+                    if allowed(&self.todos[idx], &status) {
+                        self.set_todo_status(idx, &status, mode);
+                        self.reselect();
+                        changed += 1;
+                    } else {
+                        blocked += 1;
+                    }
+                }
+            }
+            Mode::Visual => {
+                let mut created = 0;
+                // Backwards so occurrences inserted below don't shift the todos still to change
+                for i in self.visual_selection().into_iter().rev() {
+                    let status = pick(&self.todos[i]);
+                    if !allowed(&self.todos[i], &status) {
+                        blocked += 1;
+                        continue;
+                    }
+                    if self.set_todo_status(i, &status, mode) {
+                        created += 1;
+                    }
+                    changed += 1;
+                }
+                if self.visual_range.start > self.visual_range.end {
+                    self.visual_range.start += created;
+                } else {
+                    self.visual_range.end += created;
+                }
+                self.todo_idx = Some(self.visual_range.end);
+            }
+            _ => unreachable!("changing status only support normal and visual mode")
+        }
+        (changed, blocked)
+    }
+
+    // Finishing a recurring todo creates its next occurrence right below it and its subtasks,
+    // unless the one created last time is still around. Returns whether one was created
+    fn set_todo_status(&mut self, idx: usize, status: &Status, mode: Mode) -> bool {
+        let todo = &mut self.todos[idx];
+        let was_finished = todo.completed;
+        todo.set_status(status);
+        if was_finished || !todo.completed {
+            return false;
+        }
+        if let Some(spawned) = todo.spawned {
+            if self.todos.iter().any(|todo| todo.id == spawned) {
+                return false;
+            }
         }
+        let todo = &mut self.todos[idx];
+        match todo.next_occurrence() {
+            Some(mut next) => {
+                todo.spawned = Some(next.id);
+                next.selected = mode == Mode::Visual;
+                let pos = self.subtree_end(idx);
+                self.todos.insert(pos, next);
+                self.reindex();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn raise_priority(&mut self, mode: Mode) {
+        self.change_priority(mode, Priority::raise);
     }
 
-    pub fn toggle_completed(&mut self, mode: Mode) {
+    pub fn lower_priority(&mut self, mode: Mode) {
+        self.change_priority(mode, Priority::lower);
+    }
+
+    fn change_priority(&mut self, mode: Mode, change: fn(Priority) -> Priority) {
         match mode {
             Mode::Normal => {
                 if let Some(todo) = self.current_todo() {
-                    todo.completed ^= true;
+                    todo.priority = change(todo.priority);
+                    todo.touch();
                 }
             }
             Mode::Visual => {
-                let lower = self.visual_range.lower();
-                let upper = self.visual_range.uppser();
-                for i in lower..=upper {
-                    self.todos[i].completed ^= true;
+                for i in self.visual_selection() {
+                    let todo = &mut self.todos[i];
+                    todo.priority = change(todo.priority);
+                    todo.touch();
                 }
             }
-            _ => unreachable!("toggling completed only support normal and visual mode")
+            _ => unreachable!("changing priority only supports normal and visual mode")
+        }
+    }
+
+    // Stable sort of the top level todos and of the subtasks of every todo, subtasks move
+    // along with their parent. The selection follows the todo it was on
+    pub fn sort_by<F>(&mut self, mut compare: F)
+    where
+        F: FnMut(&Todo, &Todo) -> Ordering,
+    {
+        self.normalize_depths();
+        let mut order = Vec::with_capacity(self.todos.len());
+        self.sorted_order(0, self.todos.len(), &mut compare, &mut order);
+        let mut todos: Vec<Option<Todo>> = std::mem::take(&mut self.todos).into_iter().map(Some).collect();
+        self.todos = order.iter().map(|&i| todos[i].take().expect("each todo is moved once")).collect();
+        for (i, todo) in self.todos.iter_mut().enumerate() {
+            todo.todo_idx = i;
+        }
+        if let Some(idx) = self.todo_idx {
+            self.todo_idx = order.iter().position(|&i| i == idx);
+        }
+    }
+
+    // Pushes the indices of the siblings in `start..end` in sorted order, each followed by
+    // its own sorted subtasks
+    fn sorted_order<F>(&self, start: usize, end: usize, compare: &mut F, order: &mut Vec<usize>)
+    where
+        F: FnMut(&Todo, &Todo) -> Ordering,
+    {
+        let mut siblings = Vec::new();
+        let mut i = start;
+        while i < end {
+            siblings.push(i);
+            i = self.subtree_end(i);
+        }
+        siblings.sort_by(|&a, &b| compare(&self.todos[a], &self.todos[b]));
+        for i in siblings {
+            order.push(i);
+            self.sorted_order(i + 1, self.subtree_end(i), compare, order);
+        }
+    }
+
+    pub fn set_due(&mut self, due: Option<Due>) {
+        if let Some(todo) = self.current_todo() {
+            todo.due = due;
+            todo.touch();
+        }
+    }
+
+    pub fn set_estimate(&mut self, estimate: Option<Estimate>) {
+        if let Some(todo) = self.current_todo() {
+            todo.estimate = estimate;
+            todo.touch();
+        }
+    }
+
+    pub fn set_recurrence(&mut self, recurrence: Option<Recurrence>) {
+        if let Some(todo) = self.current_todo() {
+            todo.recurrence = recurrence;
+            todo.touch();
         }
     }
 
@@ -328,6 +928,7 @@ impl TodoList{
         else {
             let todo = self.current_todo().expect("Editing title must be set to true if insert mode and no todo is selected");
             todo.value.pop();
+            todo.touch();
         }
     }
 
@@ -339,22 +940,58 @@ impl TodoList{
         else {
             let todo = self.current_todo().expect("Editing title must be set to true if insert mode and no todo is selected");
             todo.value.push(c);
+            todo.touch();
         }
     }
 }
 
 use ratatui::prelude::*;
-use ratatui::widgets::{List, ListItem};
-use ratatui::widgets::{Block};
-impl Widget for &TodoList {
+use ratatui::widgets::{List, ListItem, ListState};
+use ratatui::widgets::{Block, Scrollbar, ScrollbarOrientation, ScrollbarState};
+
+// Splits a line of a todo so its `#tag` words stand out
+fn highlight_tags(text: &str, style: Style) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    for (i, word) in text.split(' ').enumerate() {
+        if i > 0 {
+            spans.push(Span::styled(" ", style));
+        }
+        let word_style = if todo::tag_name(word).is_some() { style.magenta() } else { style };
+        spans.push(Span::styled(word.to_string(), word_style));
+    }
+    spans
+}
+
+// `completed/total` or a percentage followed by a gauge of `width` cells
+fn progress(completed: usize, total: usize, percent: bool, width: usize) -> String {
+    let filled = (completed * width).checked_div(total).unwrap_or(0);
+    let gauge = "█".repeat(filled) + &"░".repeat(width - filled);
+    if percent {
+        format!(" {}% {} ", (completed * 100).checked_div(total).unwrap_or(0), gauge)
+    } else {
+        format!(" {}/{} {} ", completed, total, gauge)
+    }
+}
+
+// A todolist paired with the settings that affect how it is drawn
+pub struct TodoListView<'a> {
+    pub todolist: &'a TodoList,
+    pub settings: &'a Settings,
+    // Ids of every open todo, to tell which todos are blocked
+    pub open_ids: &'a HashSet<u64>,
+}
+
+impl Widget for TodoListView<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
+        let todolist = self.todolist;
+
         // Todolist Title
-        let title = Span::from(self.title.as_str());
+        let title = Span::from(todolist.title.as_str());
         let mut cursor = Span::raw("");
-        if self.selected && self.editing_title {
+        if todolist.selected && todolist.editing_title {
             cursor = Span::from(" ").bg(Color::White);
         }
-        let title_style = if self.todo_idx.is_none() {
+        let title_style = if todolist.todo_idx.is_none() {
             Color::Yellow
         } else{
             Color::White
@@ -363,9 +1000,18 @@ impl Widget for &TodoList {
             .style(title_style)
             .centered();
 
-        // Todo items
-        let todo_items = self.todos.iter().map(|todo| {
-            let status = if todo.completed { " [x] " } else { " [ ] " };
+        // Todo items, hidden todos are left out entirely
+        let visible: Vec<usize> = (0..todolist.todos.len()).filter(|&i| todolist.is_visible(i)).collect();
+        let todo_items = visible.iter().map(|&idx| {
+            let todo = &todolist.todos[idx];
+            let status = status::status_of(&self.settings.statuses, todo);
+            let checkbox = format!("[{}] ", status.glyph);
+            let (marker, marker_color) = match todo.priority {
+                Priority::None => (" ", Color::Reset),
+                Priority::Low => ("!", Color::Blue),
+                Priority::Medium => ("!", Color::Yellow),
+                Priority::High => ("!", Color::Red),
+            };
 
             let mut content_style = Style::default();
             let mut span_style = Style::default();
@@ -374,6 +1020,11 @@ impl Widget for &TodoList {
                 content_style = content_style.add_modifier(Modifier::CROSSED_OUT);
             }
 
+            let blocked = todo.is_blocked(self.open_ids);
+            if blocked {
+                content_style = content_style.dark_gray();
+            }
+
             if todo.editing {
                 content_style = content_style.add_modifier(Modifier::UNDERLINED);
             }
@@ -393,17 +1044,43 @@ impl Widget for &TodoList {
                 todo.value.clone()
             };
 
+            // Tree guides in front of subtasks, one column pair per level of nesting
+            let guides = todolist.tree_guides(idx);
+            let first_guides: String = guides.iter().enumerate().map(|(level, &more)| {
+                match (level + 1 == guides.len(), more) {
+                    (true, true) => "├ ",
+                    (true, false) => "└ ",
+                    (false, true) => "│ ",
+                    (false, false) => "  ",
+                }
+            }).collect();
+            let rest_guides: String = guides.iter().map(|&more| if more { "│ " } else { "  " }).collect();
+
+            // Estimate right-aligned on the first line, the value wraps before it
+            let estimate = todo.estimate.map(|estimate| format!(" {}", estimate)).unwrap_or_default();
+            let estimate_width = estimate.chars().count() as u16;
+
             // Creating text wrapping through the textwrap library
             let border_width = 2;
             let status_width = 5;
             let cursor_width = 1;
-            let width = area.width.saturating_sub(border_width + status_width + cursor_width) as usize;
+            let guide_width = 2 * guides.len() as u16;
+            let width = area.width.saturating_sub(border_width + status_width + cursor_width + guide_width + estimate_width) as usize;
             let wrapped = textwrap::wrap(&display_value, width.max(1));
             let last = wrapped.len().saturating_sub(1);
-            let lines = wrapped.into_iter()
+            let last_width = wrapped.last().map_or(0, |text| text.chars().count());
+            let mut lines = wrapped.into_iter()
                 .enumerate()
                 .map(|(i, text)| {
-                    let prefix = if i == 0 { status } else { "     " };
+                    let prefix = if i == 0 {
+                        vec![
+                            Span::raw(first_guides.clone()).dark_gray(),
+                            Span::raw(marker).fg(marker_color).bold(),
+                            Span::raw(checkbox.clone()),
+                        ]
+                    } else {
+                        vec![Span::raw(rest_guides.clone()).dark_gray(), Span::raw("     ")]
+                    };
                     let cursor = if todo.editing && i == last {
                         Span::from(" ").bg(Color::White)
                     } 
@@ -411,23 +1088,184 @@ impl Widget for &TodoList {
                         Span::raw("")
                     };
 
-                    Line::from(vec![
-                        Span::raw(prefix),
-                        Span::styled(text.into_owned(), content_style),
-                        cursor,
-                    ])
+                    let mut spans = prefix;
+                    spans.extend(highlight_tags(&text, content_style));
+                    spans.push(cursor);
+                    Line::from(spans)
                     
                 })
             .collect::<Vec<_>>();
+
+            // Lock, timer, subtask progress and due date after the value, on their own line if they don't fit
+            let mut trailing = Vec::new();
+            if blocked {
+                trailing.push(Span::from(" 🔒"));
+            }
+            if todo.timer.is_some() {
+                trailing.push(Span::from(" ⏱").green());
+            }
+            if todolist.has_subtasks(idx) {
+                let (completed, total) = todolist.subtask_progress(idx);
+                let fold = if todo.collapsed { " ▸" } else { "" };
+                trailing.push(Span::from(format!("{} {}/{}", fold, completed, total)).dark_gray());
+            }
+            if let (Some(due), Some(urgency)) = (todo.due, todo.urgency()) {
+                let due_color = match urgency {
+                    Urgency::Overdue => Color::Red,
+                    Urgency::Today => Color::Yellow,
+                    Urgency::Upcoming => Color::Cyan,
+                };
+                let due_text = match &todo.recurrence {
+                    Some(recurrence) => format!(" @{} ↻ {}", due, recurrence),
+                    None => format!(" @{}", due),
+                };
+                trailing.push(Span::from(due_text).fg(due_color));
+            }
+            if !trailing.is_empty() {
+                let trailing_width: usize = trailing.iter().map(Span::width).sum();
+                // The first line is shared with the estimate
+                let width = if lines.len() == 1 { width } else { width + estimate_width as usize };
+                if last_width + trailing_width <= width {
+                    if let Some(line) = lines.last_mut() {
+                        line.spans.extend(trailing);
+                    }
+                } else {
+                    let mut line = vec![Span::raw(rest_guides.clone()).dark_gray(), Span::raw("    ")];
+                    line.extend(trailing);
+                    lines.push(Line::from(line));
+                }
+            }
+            if !estimate.is_empty() {
+                if let Some(line) = lines.first_mut() {
+                    let pad = (area.width.saturating_sub(border_width + estimate_width) as usize).saturating_sub(line.width());
+                    line.spans.push(Span::raw(" ".repeat(pad)));
+                    line.spans.push(Span::raw(estimate).cyan());
+                }
+            }
             ListItem::new(lines).style(span_style)
-        });
+        }).collect::<Vec<_>>();
+        let heights = todo_items.iter().map(ListItem::height).collect::<Vec<_>>();
 
         // Todolist UI Component
-        let todolist_color = if self.selected {Color::Yellow} else {Color::White};
-        let todolist_block = Block::bordered().title(block_title).border_style(todolist_color);
-        let todo_list = List::new(todo_items).block(todolist_block);
+        let todolist_color = if todolist.selected {Color::Yellow} else {Color::White};
+        let mut todolist_block = Block::bordered().title(block_title).border_style(todolist_color);
+        // Progress and estimates on the left of the bottom border and the hidden count on
+        // the right, dropping whatever doesn't fit, estimates first
+        let available = area.width.saturating_sub(2) as usize;
+        let (open, total) = todolist.counts();
+        let mut left = Vec::new();
+        if total > 0 {
+            left.push(Span::raw(progress(total - open, total, self.settings.percent, 8)));
+        }
+        let hidden_completed = todolist.todos.iter().enumerate()
+            .filter(|(i, todo)| todo.completed && !todolist.is_visible(*i))
+            .count();
+        let hidden = (hidden_completed > 0).then(|| Span::raw(format!(" {} completed hidden ", hidden_completed)));
+        let hidden_width = hidden.as_ref().map_or(0, Span::width);
+        let (open_effort, completed_effort) = todolist.effort();
+        if !open_effort.is_empty() || !completed_effort.is_empty() {
+            let effort = Span::raw(format!(" Σ {} open · {} done ", open_effort, completed_effort)).dark_gray();
+            if left.iter().map(Span::width).sum::<usize>() + effort.width() + hidden_width <= available {
+                left.push(effort);
+            }
+        }
+        let left = Line::from(left);
+        let left_width = left.width();
+        if left_width > 0 {
+            todolist_block = todolist_block.title_bottom(left.left_aligned());
+        }
+        if let Some(hidden) = hidden {
+            if left_width + hidden_width <= available {
+                todolist_block = todolist_block.title_bottom(Line::from(hidden).right_aligned());
+            }
+        }
+        let inner = todolist_block.inner(area);
+        let todo_list = List::new(todo_items)
+            .block(todolist_block)
+            .scroll_padding(self.settings.scrolloff);
+
+        // The list widget moves the offset so the selection stays in view,
+        // we remember it so scrolling is stable between frames
+        let selected = todolist.todo_idx.and_then(|idx| visible.iter().position(|&i| i == idx));
+        let mut state = ListState::default()
+            .with_offset(todolist.scroll_offset.get())
+            .with_selected(selected);
+        StatefulWidget::render(todo_list, area, buf, &mut state);
+        todolist.scroll_offset.set(state.offset());
+        todolist.viewport_height.set(inner.height as usize);
+
+        // Remember where everything landed for mouse hit testing
+        todolist.area.set(area);
+        let mut todo_areas = todolist.todo_areas.borrow_mut();
+        todo_areas.clear();
+        let mut y = inner.y;
+        for (idx, height) in visible.iter().zip(&heights).skip(state.offset()) {
+            if y >= inner.bottom() {
+                break;
+            }
+            let height = min(*height as u16, inner.bottom() - y);
+            todo_areas.push((*idx, Rect::new(inner.x, y, inner.width, height)));
+            y += height;
+        }
 
-        Widget::render(todo_list, area, buf);
+        // Scrollbar on the right border when the todos overflow the pane
+        let len = visible.len();
+        if len > inner.height as usize {
+            let mut scrollbar_state = ScrollbarState::new(len)
+                .position(selected.unwrap_or(state.offset()));
+            let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .begin_symbol(None)
+                .end_symbol(None)
+                .style(todolist_color);
+            let scrollbar_area = area.inner(Margin { vertical: 1, horizontal: 0 });
+            StatefulWidget::render(scrollbar, scrollbar_area, buf, &mut scrollbar_state);
+        }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A todolist with a todo per entry, `(text, depth)`
+    fn todolist(todos: &[(&str, usize)]) -> TodoList {
+        let mut todolist = TodoList::new();
+        for (i, (value, depth)) in todos.iter().enumerate() {
+            let mut todo = Todo::new(i);
+            todo.value = value.to_string();
+            todo.depth = *depth;
+            todolist.insert_todo(i, todo);
+        }
+        todolist
+    }
+
+    fn values(todolist: &TodoList) -> Vec<&str> {
+        todolist.todos().iter().map(|todo| todo.value.as_str()).collect()
+    }
+
+    #[test]
+    fn moves_past_hidden_todos_without_taking_them_along() {
+        let mut todolist = todolist(&[("a #x", 0), ("a1", 1), ("b", 0), ("c", 0), ("d #x", 0), ("d1", 1)]);
+        todolist.visibility.tag = Some(String::from("x"));
+        todolist.select_todo(4);
+        assert!(todolist.move_todo_up(Mode::Normal));
+        assert_eq!(values(&todolist), ["d #x", "d1", "b", "c", "a #x", "a1"]);
+        assert_eq!(todolist.todo_idx, Some(0));
+        assert!(!todolist.move_todo_up(Mode::Normal));
+
+        assert!(todolist.move_todo_down(Mode::Normal));
+        assert_eq!(values(&todolist), ["a #x", "a1", "b", "c", "d #x", "d1"]);
+        assert_eq!(todolist.todo_idx, Some(4));
+        assert!(!todolist.move_todo_down(Mode::Normal));
+    }
+
+    #[test]
+    fn moves_subtasks_among_their_siblings() {
+        let mut todolist = todolist(&[("p", 0), ("a", 1), ("b", 1), ("b1", 2), ("q", 0)]);
+        todolist.select_todo(2);
+        assert!(todolist.move_todo_up(Mode::Normal));
+        assert_eq!(values(&todolist), ["p", "b", "b1", "a", "q"]);
+        assert!(!todolist.move_todo_up(Mode::Normal));
+    }
+}